@@ -25,6 +25,13 @@ export const languageOptions: LanguageOption[] = [
 	// Python Languages
 	{ value: ProgramLanguage.PYTHON2, label: 'Python 2' },
 	{ value: ProgramLanguage.PYTHON3, label: 'Python 3' },
+	{ value: ProgramLanguage.PYPY3, label: 'PyPy 3' },
+	// Other Languages
+	{ value: ProgramLanguage.RUST, label: 'Rust' },
+	{ value: ProgramLanguage.GO, label: 'Go' },
+	{ value: ProgramLanguage.KOTLIN, label: 'Kotlin' },
+	{ value: ProgramLanguage.CSHARP, label: 'C#' },
+	{ value: ProgramLanguage.JAVASCRIPT, label: 'JavaScript (Node.js)' },
 ];
 
 export const getLanguageColor = (language: ProgramLanguage): string => {
@@ -33,6 +40,11 @@ export const getLanguageColor = (language: ProgramLanguage): string => {
 		cpp: '#00599C',
 		java: '#ED8B00',
 		python: '#3776AB',
+		rust: '#DEA584',
+		go: '#00ADD8',
+		kotlin: '#7F52FF',
+		csharp: '#68217A',
+		javascript: '#F7DF1E',
 	};
 
 	const languageMap: Record<ProgramLanguage, { base: string; shade: number }> = {
@@ -58,6 +70,14 @@ export const getLanguageColor = (language: ProgramLanguage): string => {
 		// Python Languages
 		[ProgramLanguage.PYTHON2]: { base: 'python', shade: 0.8 },
 		[ProgramLanguage.PYTHON3]: { base: 'python', shade: 1.0 },
+		[ProgramLanguage.PYPY3]: { base: 'python', shade: 1.2 },
+
+		// Other Languages
+		[ProgramLanguage.RUST]: { base: 'rust', shade: 1.0 },
+		[ProgramLanguage.GO]: { base: 'go', shade: 1.0 },
+		[ProgramLanguage.KOTLIN]: { base: 'kotlin', shade: 1.0 },
+		[ProgramLanguage.CSHARP]: { base: 'csharp', shade: 1.0 },
+		[ProgramLanguage.JAVASCRIPT]: { base: 'javascript', shade: 1.0 },
 	};
 
 	const { base, shade } = languageMap[language];
@@ -34,6 +34,13 @@ export enum ProgramLanguage {
 
 	PYTHON2 = 'PYTHON2',
 	PYTHON3 = 'PYTHON3',
+	PYPY3 = 'PYPY3',
+
+	RUST = 'RUST',
+	GO = 'GO',
+	KOTLIN = 'KOTLIN',
+	CSHARP = 'CSHARP',
+	JAVASCRIPT = 'JAVASCRIPT',
 }
 
 export interface Submission {
@@ -0,0 +1,257 @@
+use lapin::{BasicProperties, Channel, options::BasicPublishOptions};
+use std::collections::VecDeque;
+use std::error::Error;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::outbox::{Outbox, OutboxMessage};
+
+/// Messages waiting to be sent. At most `max_pending` of them are kept in
+/// memory; the rest are only in the outbox and are loaded once there is room.
+struct Pending {
+    messages: VecDeque<OutboxMessage>,
+    /// Whether the outbox holds messages that are not in memory, from
+    /// `unloaded` on.
+    spilled: bool,
+    unloaded: u64,
+}
+
+impl Pending {
+    /// Load messages that are only in the outbox once memory is free.
+    fn refill(&mut self, outbox: &Outbox, max_pending: usize) {
+        if !self.spilled || !self.messages.is_empty() {
+            return;
+        }
+
+        let loaded = outbox.load(self.unloaded, max_pending);
+        if loaded.len() < max_pending {
+            self.spilled = false;
+        }
+        if let Some(last) = loaded.last() {
+            self.unloaded = last.seq + 1;
+        }
+        self.messages.extend(loaded);
+    }
+}
+
+/// Publishes messages on the current RabbitMQ channel. Messages that must not
+/// be lost go through the on-disk outbox and are only dropped from it once
+/// RabbitMQ confirms them; while the judger is disconnected they wait there
+/// and are sent, in order, once a new channel is attached.
+pub struct Broker {
+    channel: RwLock<Option<Channel>>,
+    pending: Mutex<Pending>,
+    outbox: Outbox,
+    max_pending: usize,
+}
+
+impl Broker {
+    /// Messages left in the outbox by a previous run are sent first.
+    pub fn new(outbox: Outbox, max_pending: usize) -> Broker {
+        let mut pending = Pending {
+            messages: VecDeque::new(),
+            spilled: true,
+            unloaded: 0,
+        };
+        pending.refill(&outbox, max_pending);
+
+        Broker {
+            channel: RwLock::new(None),
+            pending: Mutex::new(pending),
+            outbox,
+            max_pending,
+        }
+    }
+
+    /// Use `channel` for publishing and send everything buffered so far. The
+    /// channel must be in confirm mode.
+    pub async fn attach(&self, channel: Channel) {
+        *self.channel.write().await = Some(channel);
+
+        let mut pending = self.pending.lock().await;
+        if !pending.messages.is_empty() {
+            info!("Sending {} buffered messages", pending.messages.len());
+        }
+        self.flush(&mut pending).await;
+    }
+
+    pub async fn detach(&self) {
+        *self.channel.write().await = None;
+    }
+
+    /// Publish a message that must not be lost. It is stored in the outbox
+    /// first, so it is sent eventually even if the judger restarts. Once the
+    /// buffer in memory is full, further messages wait only in the outbox.
+    pub async fn publish(&self, queue: &str, payload: Vec<u8>) {
+        let mut pending = self.pending.lock().await;
+
+        let message = self.outbox.store(queue, &payload);
+        if message.stored && (pending.spilled || pending.messages.len() >= self.max_pending) {
+            if !pending.spilled {
+                warn!(
+                    "Publish buffer is full ({} messages), keeping further messages on disk",
+                    pending.messages.len()
+                );
+                pending.spilled = true;
+                pending.unloaded = message.seq;
+            }
+        } else {
+            pending.messages.push_back(message);
+        }
+
+        self.flush(&mut pending).await;
+    }
+
+    /// Send messages that are still waiting, e.g. after RabbitMQ refused one.
+    pub async fn retry(&self) {
+        let mut pending = self.pending.lock().await;
+        self.flush(&mut pending).await;
+    }
+
+    /// Publish a message that is only meaningful right now, such as a
+    /// heartbeat. It is dropped if the judger is disconnected.
+    pub async fn publish_volatile(&self, queue: &str, payload: &[u8]) {
+        if let Err(e) = self.try_publish(queue, payload, false).await {
+            debug!("Dropping message for {}: {}", queue, e);
+        }
+    }
+
+    async fn flush(&self, pending: &mut Pending) {
+        loop {
+            pending.refill(&self.outbox, self.max_pending);
+            let Some(message) = pending.messages.front() else {
+                return;
+            };
+
+            if let Err(e) = self
+                .try_publish(&message.queue, &message.payload, true)
+                .await
+            {
+                warn!(
+                    "Failed to publish to {}, {} messages buffered: {}",
+                    message.queue,
+                    pending.messages.len(),
+                    e
+                );
+                return;
+            }
+            self.outbox.remove(message.seq);
+            pending.messages.pop_front();
+        }
+    }
+
+    async fn try_publish(
+        &self,
+        queue: &str,
+        payload: &[u8],
+        confirmed: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let channel = self.channel.read().await;
+        let channel = channel.as_ref().ok_or("not connected to RabbitMQ")?;
+
+        let properties = if confirmed {
+            // Persistent, so a confirmed message also survives a broker restart.
+            BasicProperties::default().with_delivery_mode(2)
+        } else {
+            BasicProperties::default()
+        };
+
+        let confirm = channel
+            .basic_publish(
+                "",
+                queue,
+                BasicPublishOptions::default(),
+                payload,
+                properties,
+            )
+            .await?;
+
+        if confirmed && !confirm.await?.is_ack() {
+            return Err("RabbitMQ did not confirm the message".into());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn outbox_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("judger-broker-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn payloads(messages: &[OutboxMessage]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|message| String::from_utf8_lossy(&message.payload).into_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn overflowing_the_buffer_keeps_every_result_in_the_outbox() {
+        let dir = outbox_dir("overflow");
+        let broker = Broker::new(Outbox::open(&dir).unwrap(), 2);
+
+        // Disconnected, so nothing is sent.
+        for i in 0..5 {
+            broker
+                .publish("judger.result", format!("result {}", i).into_bytes())
+                .await;
+        }
+
+        let expected: Vec<String> = (0..5).map(|i| format!("result {}", i)).collect();
+        assert_eq!(payloads(&broker.outbox.load(0, usize::MAX)), expected);
+
+        let mut pending = broker.pending.lock().await;
+        assert_eq!(pending.messages.len(), 2);
+
+        // Confirmed one by one, the rest is loaded in order.
+        let mut sent = vec![];
+        loop {
+            pending.refill(&broker.outbox, broker.max_pending);
+            let Some(message) = pending.messages.pop_front() else {
+                break;
+            };
+            assert!(pending.messages.len() < 2);
+            broker.outbox.remove(message.seq);
+            sent.push(message);
+        }
+        assert_eq!(payloads(&sent), expected);
+        assert!(broker.outbox.load(0, usize::MAX).is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn messages_left_by_a_previous_run_are_loaded_up_to_the_limit() {
+        let dir = outbox_dir("recovered");
+        let outbox = Outbox::open(&dir).unwrap();
+        for i in 0..3 {
+            outbox.store("judger.result", format!("result {}", i).as_bytes());
+        }
+        drop(outbox);
+
+        let broker = Broker::new(Outbox::open(&dir).unwrap(), 2);
+        let mut pending = broker.pending.lock().await;
+        assert_eq!(
+            payloads(pending.messages.make_contiguous()),
+            ["result 0", "result 1"]
+        );
+
+        // A new result goes after the ones still on disk.
+        drop(pending);
+        broker.publish("judger.result", b"result 3".to_vec()).await;
+        let pending = broker.pending.lock().await;
+        assert_eq!(pending.messages.len(), 2);
+        assert!(pending.spilled);
+        assert_eq!(broker.outbox.load(pending.unloaded, usize::MAX).len(), 2);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,473 @@
+use aws_sdk_s3::Client;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::env_tool;
+use crate::minio::{TEST_CASES_BUCKET, download_object_to_file, list_object_etags};
+use crate::status::free_disk_bytes;
+
+const INDEX_FILE: &str = "index.json";
+/// Extension of files being downloaded.
+const PART_EXTENSION: &str = "part";
+
+#[derive(Deserialize, Serialize, Clone)]
+struct CachedObject {
+    etag: String,
+    size: u64,
+}
+
+/// On-disk description of one cached problem. Keys are object keys relative
+/// to the problem prefix, e.g. `subtask1/test1/input`.
+#[derive(Deserialize, Serialize, Default)]
+struct ProblemIndex {
+    last_used: i64,
+    objects: HashMap<String, CachedObject>,
+}
+
+impl ProblemIndex {
+    fn load(dir: &Path) -> ProblemIndex {
+        fs::read_to_string(dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, dir: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tmp_path = dir.join(format!("{}.tmp", INDEX_FILE));
+        fs::write(&tmp_path, serde_json::to_vec(self)?)?;
+        fs::rename(tmp_path, dir.join(INDEX_FILE))?;
+        Ok(())
+    }
+
+    fn total_size(&self) -> u64 {
+        self.objects.values().map(|object| object.size).sum()
+    }
+
+    /// Drop the objects of `dir` whose ETag differs from `remote` or that are
+    /// missing from it or from disk. Returns their keys.
+    fn invalidate(&mut self, dir: &Path, remote: &HashMap<String, String>) -> Vec<String> {
+        let stale: Vec<String> = self
+            .objects
+            .iter()
+            .filter(|(key, cached)| {
+                remote.get(*key) != Some(&cached.etag) || !dir.join(key.as_str()).is_file()
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in &stale {
+            let _ = fs::remove_file(dir.join(key));
+            self.objects.remove(key);
+        }
+        stale
+    }
+}
+
+/// Remove the file being downloaded to unless the download finished, also
+/// when the download is dropped halfway, e.g. by an aborted prefetch.
+struct PartialDownload<'a> {
+    path: &'a Path,
+    finished: bool,
+}
+
+impl Drop for PartialDownload<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = fs::remove_file(self.path);
+        }
+    }
+}
+
+/// Remove downloads under `dir` that were interrupted by a crash.
+fn remove_partial_downloads(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        if path.is_dir() {
+            remove_partial_downloads(&path);
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == PART_EXTENSION)
+        {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+/// Local copy of the `test-cases` bucket, one directory per problem, bounded
+/// by a disk budget and evicted least-recently-used first.
+pub struct TestDataCache {
+    root: PathBuf,
+    max_bytes: u64,
+}
+
+impl TestDataCache {
+    pub fn from_env() -> TestDataCache {
+        let root = env_tool::env_or_default("JUDGER_CACHE_DIR", "/var/local/lib/judger/cache");
+        let max_mb = env_tool::env_or_default("JUDGER_CACHE_MAX_MB", "2048")
+            .parse::<u64>()
+            .unwrap_or(2048);
+
+        TestDataCache {
+            root: PathBuf::from(root),
+            max_bytes: max_mb * 1024 * 1024,
+        }
+    }
+
+    /// Free bytes left on the filesystem holding the cache.
+    pub fn free_bytes(&self) -> Option<u64> {
+        fs::create_dir_all(&self.root).ok()?;
+        free_disk_bytes(&self.root)
+    }
+
+    /// Validate the cached files of a problem against the ETags currently in
+    /// MinIO, dropping anything that changed or disappeared upstream.
+    pub async fn open_problem(
+        &self,
+        client: &Client,
+        problem_id: &Uuid,
+    ) -> Result<ProblemCache, Box<dyn Error + Send + Sync>> {
+        let dir = self.root.join(problem_id.to_string());
+        fs::create_dir_all(&dir)?;
+
+        let prefix = format!("{}/", problem_id);
+        let remote: HashMap<String, String> = list_object_etags(client, TEST_CASES_BUCKET, &prefix)
+            .await?
+            .into_iter()
+            .filter_map(|(key, etag)| key.strip_prefix(&prefix).map(|key| (key.to_string(), etag)))
+            .collect();
+
+        remove_partial_downloads(&dir);
+        let mut index = ProblemIndex::load(&dir);
+        let stale = index.invalidate(&dir, &remote);
+        for key in &stale {
+            debug!("Dropped stale cache entry {}/{}", problem_id, key);
+        }
+
+        index.last_used = Utc::now().timestamp();
+        index.save(&dir)?;
+
+        info!(
+            "Opened test data cache for problem {}: {} cached, {} invalidated",
+            problem_id,
+            index.objects.len(),
+            stale.len()
+        );
+
+        Ok(ProblemCache {
+            problem_id: *problem_id,
+            dir,
+            remote,
+            index: Mutex::new(index),
+        })
+    }
+
+    /// Remove least recently used problems until the cache fits the budget.
+    /// The problem currently being judged is never evicted.
+    pub fn evict(&self, keep: &Uuid) {
+        let Ok(entries) = fs::read_dir(&self.root) else {
+            return;
+        };
+
+        let mut problems: Vec<(PathBuf, i64, u64)> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .map(|path| {
+                let index = ProblemIndex::load(&path);
+                (path, index.last_used, index.total_size())
+            })
+            .collect();
+
+        let mut total: u64 = problems.iter().map(|(_, _, size)| size).sum();
+        if total <= self.max_bytes {
+            return;
+        }
+
+        problems.sort_by_key(|(_, last_used, _)| *last_used);
+
+        for (path, _, size) in problems {
+            if total <= self.max_bytes {
+                break;
+            }
+            if path.file_name().and_then(|name| name.to_str()) == Some(keep.to_string().as_str()) {
+                continue;
+            }
+
+            info!("Evicting cached test data: {}", path.display());
+            match fs::remove_dir_all(&path) {
+                Ok(()) => total = total.saturating_sub(size),
+                Err(e) => warn!("Failed to evict {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+/// Cached test data of a single problem, valid for the duration of one job.
+pub struct ProblemCache {
+    problem_id: Uuid,
+    dir: PathBuf,
+    remote: HashMap<String, String>,
+    index: Mutex<ProblemIndex>,
+}
+
+impl ProblemCache {
+    /// Return the local path of `<slug>/<name>`, downloading it first if it
+    /// is not cached yet.
+    pub async fn fetch(
+        &self,
+        client: &Client,
+        slug: &str,
+        name: &str,
+    ) -> Result<PathBuf, Box<dyn Error + Send + Sync>> {
+        let key = format!("{}/{}", slug, name);
+        let path = self.dir.join(&key);
+
+        if self.index.lock().unwrap().objects.contains_key(&key) {
+            debug!("Cache hit for {}/{}", self.problem_id, key);
+            return Ok(path);
+        }
+
+        let remote_etag = self
+            .remote
+            .get(&key)
+            .ok_or_else(|| format!("Test data {}/{} not found", self.problem_id, key))?;
+
+        debug!("Cache miss for {}/{}, downloading", self.problem_id, key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Download next to the final path and rename, so a failed or aborted
+        // download never leaves a truncated file behind.
+        let tmp_path = path.with_extension(PART_EXTENSION);
+        let mut partial = PartialDownload {
+            path: &tmp_path,
+            finished: false,
+        };
+        let object_key = format!("{}/{}", self.problem_id, key);
+        let (etag, size) =
+            download_object_to_file(client, TEST_CASES_BUCKET, &object_key, &tmp_path).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        partial.finished = true;
+
+        let mut index = self.index.lock().unwrap();
+        index.objects.insert(
+            key,
+            CachedObject {
+                etag: etag.unwrap_or_else(|| remote_etag.clone()),
+                size,
+            },
+        );
+        index.save(&self.dir)?;
+
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path =
+                std::env::temp_dir().join(format!("judger-cache-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Cache a problem of `size` bytes last used at `last_used`.
+    fn add_problem(root: &Path, id: u128, last_used: i64, size: u64) -> Uuid {
+        let id = Uuid::from_u128(id);
+        let dir = root.join(id.to_string());
+        fs::create_dir_all(dir.join("1/1")).unwrap();
+        fs::write(dir.join("1/1/input"), vec![b'x'; size as usize]).unwrap();
+        ProblemIndex {
+            last_used,
+            objects: HashMap::from([(
+                "1/1/input".to_string(),
+                CachedObject {
+                    etag: "etag".to_string(),
+                    size,
+                },
+            )]),
+        }
+        .save(&dir)
+        .unwrap();
+        id
+    }
+
+    fn cached(root: &Path) -> Vec<Uuid> {
+        let mut ids: Vec<Uuid> = fs::read_dir(root)
+            .unwrap()
+            .map(|entry| {
+                entry
+                    .unwrap()
+                    .file_name()
+                    .to_str()
+                    .unwrap()
+                    .parse()
+                    .unwrap()
+            })
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn cache_within_budget_is_kept() {
+        let root = TempDir::new("within-budget");
+        let cache = TestDataCache {
+            root: root.0.clone(),
+            max_bytes: 300,
+        };
+        let ids = [
+            add_problem(&root.0, 1, 10, 100),
+            add_problem(&root.0, 2, 20, 100),
+            add_problem(&root.0, 3, 30, 100),
+        ];
+
+        cache.evict(&Uuid::nil());
+        assert_eq!(cached(&root.0), ids);
+    }
+
+    #[test]
+    fn least_recently_used_problems_are_evicted_first() {
+        let root = TempDir::new("lru");
+        let cache = TestDataCache {
+            root: root.0.clone(),
+            max_bytes: 250,
+        };
+        let newest = add_problem(&root.0, 1, 30, 100);
+        add_problem(&root.0, 2, 10, 100);
+        let middle = add_problem(&root.0, 3, 20, 100);
+
+        cache.evict(&Uuid::nil());
+        assert_eq!(cached(&root.0), [newest, middle]);
+    }
+
+    #[test]
+    fn eviction_stops_once_within_budget() {
+        let root = TempDir::new("budget");
+        let cache = TestDataCache {
+            root: root.0.clone(),
+            max_bytes: 150,
+        };
+        add_problem(&root.0, 1, 10, 50);
+        add_problem(&root.0, 2, 20, 50);
+        let large = add_problem(&root.0, 3, 30, 100);
+        let newest = add_problem(&root.0, 4, 40, 50);
+
+        cache.evict(&Uuid::nil());
+        assert_eq!(cached(&root.0), [large, newest]);
+    }
+
+    #[test]
+    fn problem_being_judged_is_never_evicted() {
+        let root = TempDir::new("keep");
+        let cache = TestDataCache {
+            root: root.0.clone(),
+            max_bytes: 100,
+        };
+        let judged = add_problem(&root.0, 1, 10, 150);
+        add_problem(&root.0, 2, 20, 50);
+
+        cache.evict(&judged);
+        assert_eq!(cached(&root.0), [judged]);
+    }
+
+    #[test]
+    fn changed_and_missing_objects_are_invalidated() {
+        let root = TempDir::new("invalidate");
+        let dir = &root.0;
+        let mut index = ProblemIndex::default();
+        for (key, etag) in [
+            ("1/a/input", "a"),
+            ("1/b/input", "b"),
+            ("1/c/input", "c"),
+            ("1/d/input", "d"),
+        ] {
+            fs::create_dir_all(dir.join(key).parent().unwrap()).unwrap();
+            fs::write(dir.join(key), key).unwrap();
+            index.objects.insert(
+                key.to_string(),
+                CachedObject {
+                    etag: etag.to_string(),
+                    size: key.len() as u64,
+                },
+            );
+        }
+        fs::remove_file(dir.join("1/d/input")).unwrap();
+        let remote = HashMap::from([
+            ("1/a/input".to_string(), "a".to_string()),
+            ("1/b/input".to_string(), "b2".to_string()),
+            ("1/d/input".to_string(), "d".to_string()),
+        ]);
+
+        let mut stale = index.invalidate(dir, &remote);
+        stale.sort();
+        assert_eq!(stale, ["1/b/input", "1/c/input", "1/d/input"]);
+        assert_eq!(index.objects.keys().collect::<Vec<_>>(), ["1/a/input"]);
+        assert!(dir.join("1/a/input").is_file());
+        assert!(!dir.join("1/b/input").exists());
+        assert!(!dir.join("1/c/input").exists());
+    }
+
+    #[test]
+    fn index_survives_a_reload() {
+        let root = TempDir::new("index");
+        let id = add_problem(&root.0, 1, 42, 100);
+        let index = ProblemIndex::load(&root.0.join(id.to_string()));
+        assert_eq!(index.last_used, 42);
+        assert_eq!(index.total_size(), 100);
+        assert!(!root.0.join(id.to_string()).join("index.json.tmp").exists());
+
+        let missing = ProblemIndex::load(&root.0.join("missing"));
+        assert_eq!(missing.total_size(), 0);
+    }
+
+    #[test]
+    fn partial_downloads_are_removed() {
+        let root = TempDir::new("partial");
+        let id = add_problem(&root.0, 1, 42, 100);
+        let dir = root.0.join(id.to_string());
+        fs::write(dir.join("1/1/output.part"), "trunc").unwrap();
+
+        {
+            let part = dir.join("1/1/answer.part");
+            fs::write(&part, "trunc").unwrap();
+            let _download = PartialDownload {
+                path: &part,
+                finished: false,
+            };
+        }
+        assert!(!dir.join("1/1/answer.part").exists());
+
+        remove_partial_downloads(&root.0);
+        assert!(!dir.join("1/1/output.part").exists());
+        assert!(dir.join("1/1/input").is_file());
+        assert!(dir.join(INDEX_FILE).is_file());
+    }
+}
@@ -0,0 +1,203 @@
+use futures_lite::stream::StreamExt;
+use lapin::{Consumer, options::BasicAckOptions};
+use serde::Deserialize;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{error, info};
+use uuid::Uuid;
+
+pub const CANCEL_EXCHANGE: &str = "judger.cancel";
+
+/// How long the cancel queue of a judger outlives its last connection.
+pub const CANCEL_QUEUE_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How many cancelled dispatches are remembered, so a job cancelled before it
+/// reached a judger is still skipped when it is consumed.
+const MAX_REMEMBERED: usize = 100_000;
+
+/// Queue of one judger bound to the cancel exchange.
+pub fn cancel_queue(judger_id: &str) -> String {
+    format!("{}.{}", CANCEL_EXCHANGE, judger_id)
+}
+
+#[derive(Deserialize)]
+struct CancelMessage {
+    data: CancelRequest,
+}
+
+/// Dispatches to cancel, by the ids of [`dispatch_id`].
+#[derive(Deserialize)]
+struct CancelRequest {
+    ids: Vec<String>,
+}
+
+/// Identity of one dispatch of a job, which cancel requests refer to: the
+/// message id the server sets on every job it sends, or the job id for
+/// producers that set none. A submission that is judged again after being
+/// cancelled is dispatched under a new message id, so it is not cancelled.
+pub fn dispatch_id(job_id: &Uuid, message_id: Option<&str>) -> String {
+    match message_id.filter(|message_id| !message_id.is_empty()) {
+        Some(message_id) => message_id.to_string(),
+        None => job_id.to_string(),
+    }
+}
+
+#[derive(Default)]
+struct CancelledIds {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+/// Dispatches cancelled through the `judger.cancel` broadcast exchange.
+#[derive(Default)]
+pub struct Cancellations {
+    cancelled: Mutex<CancelledIds>,
+    notify: Notify,
+}
+
+impl Cancellations {
+    pub fn cancel(&self, ids: &[String]) {
+        let mut cancelled = self.cancelled.lock().unwrap();
+        for id in ids {
+            if cancelled.ids.insert(id.clone()) {
+                cancelled.order.push_back(id.clone());
+            }
+        }
+        while cancelled.order.len() > MAX_REMEMBERED {
+            if let Some(oldest) = cancelled.order.pop_front() {
+                cancelled.ids.remove(&oldest);
+            }
+        }
+        drop(cancelled);
+
+        self.notify.notify_waiters();
+    }
+
+    fn is_cancelled(&self, id: &str) -> bool {
+        self.cancelled.lock().unwrap().ids.contains(id)
+    }
+
+    /// Resolve once `id` has been cancelled.
+    async fn cancelled(&self, id: &str) {
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.is_cancelled(id) {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Cancellation of the dispatch `dispatch_id`, for the job handling it.
+    pub fn token(&self, dispatch_id: String) -> CancelToken<'_> {
+        CancelToken {
+            cancellations: self,
+            dispatch_id,
+        }
+    }
+
+    /// Record cancel requests from `consumer` until the connection is lost.
+    pub async fn listen(&self, mut consumer: Consumer) {
+        while let Some(delivery) = consumer.next().await {
+            let delivery = match delivery {
+                Ok(delivery) => delivery,
+                Err(e) => {
+                    error!("Error receiving cancel message: {:?}", e);
+                    return;
+                }
+            };
+
+            match serde_json::from_slice::<CancelMessage>(&delivery.data) {
+                Ok(message) => {
+                    info!("Received cancel request for {} jobs", message.data.ids.len());
+                    self.cancel(&message.data.ids);
+                }
+                Err(e) => error!("Ignoring malformed cancel message: {}", e),
+            }
+
+            if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+                error!("Failed to ack cancel message: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Whether one dispatch of a job has been cancelled.
+pub struct CancelToken<'a> {
+    cancellations: &'a Cancellations,
+    dispatch_id: String,
+}
+
+impl CancelToken<'_> {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellations.is_cancelled(&self.dispatch_id)
+    }
+
+    /// Resolve once the dispatch has been cancelled.
+    pub async fn cancelled(&self) {
+        self.cancellations.cancelled(&self.dispatch_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancelling_a_dispatch_leaves_later_dispatches_of_the_job() {
+        let cancellations = Cancellations::default();
+        let job_id = Uuid::from_u128(1);
+        let first = dispatch_id(&job_id, Some("first"));
+        let second = dispatch_id(&job_id, Some("second"));
+
+        cancellations.cancel(std::slice::from_ref(&first));
+        assert!(cancellations.token(first).is_cancelled());
+
+        // Judged again, e.g. by a rejudge: runs normally.
+        assert!(!cancellations.token(second).is_cancelled());
+    }
+
+    #[test]
+    fn dispatches_without_message_id_are_cancelled_by_job_id() {
+        let cancellations = Cancellations::default();
+        let job_id = Uuid::from_u128(1);
+
+        assert_eq!(dispatch_id(&job_id, None), job_id.to_string());
+        assert_eq!(dispatch_id(&job_id, Some("")), job_id.to_string());
+        cancellations.cancel(&[job_id.to_string()]);
+        assert!(cancellations.token(dispatch_id(&job_id, None)).is_cancelled());
+        assert!(!cancellations.token(dispatch_id(&job_id, Some("new"))).is_cancelled());
+    }
+
+    #[test]
+    fn oldest_cancellations_are_forgotten() {
+        let cancellations = Cancellations::default();
+        let ids: Vec<String> = (0..=MAX_REMEMBERED).map(|i| i.to_string()).collect();
+        cancellations.cancel(&ids);
+
+        assert!(!cancellations.token("0".to_string()).is_cancelled());
+        assert!(cancellations.token("1".to_string()).is_cancelled());
+        assert!(cancellations.token(MAX_REMEMBERED.to_string()).is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn waiting_token_wakes_up_on_cancel() {
+        let cancellations = Cancellations::default();
+        let token = cancellations.token("dispatch".to_string());
+
+        let waiting = token.cancelled();
+        tokio::pin!(waiting);
+        assert!(futures_lite::future::poll_once(&mut waiting).await.is_none());
+
+        cancellations.cancel(&["other".to_string()]);
+        assert!(futures_lite::future::poll_once(&mut waiting).await.is_none());
+
+        cancellations.cancel(&["dispatch".to_string()]);
+        assert!(futures_lite::future::poll_once(&mut waiting).await.is_some());
+    }
+}
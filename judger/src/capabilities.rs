@@ -0,0 +1,125 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::env_tool;
+use crate::models::Problem;
+use crate::protocol::JobPriority;
+
+/// Topic exchange jobs are routed through, with `<priority>.<language>` keys.
+pub const JOB_EXCHANGE: &str = "judger.jobs";
+
+/// What this judger can judge, advertised in heartbeats and used to pick the
+/// job queues it pulls from.
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+pub struct Capabilities {
+    pub languages: Vec<String>,
+    /// Interactive problems are not supported by the judging pipeline yet, so
+    /// this is only advertised.
+    pub interactive: bool,
+    /// Highest problem memory limit in MB this judger accepts, if any.
+    pub max_memory_mb: Option<u64>,
+}
+
+impl Capabilities {
+    /// Read `JUDGER_LANGUAGES` (comma separated, every language by default),
+    /// `JUDGER_INTERACTIVE` and `JUDGER_MAX_MEMORY_MB` (0 means no ceiling).
+    pub fn from_env(known: Vec<String>) -> Capabilities {
+        let configured = env_tool::env_or_default("JUDGER_LANGUAGES", "");
+        let languages = if configured.trim().is_empty() {
+            known
+        } else {
+            configured
+                .split(',')
+                .map(str::trim)
+                .filter(|language| !language.is_empty())
+                .filter(|language| {
+                    let supported = known.iter().any(|name| name == language);
+                    if !supported {
+                        warn!("Ignoring unavailable language in JUDGER_LANGUAGES: {}", language);
+                    }
+                    supported
+                })
+                .map(str::to_string)
+                .collect()
+        };
+
+        let max_memory_mb = env_tool::env_or_default("JUDGER_MAX_MEMORY_MB", "0")
+            .parse::<u64>()
+            .unwrap_or(0);
+
+        Capabilities {
+            languages,
+            interactive: env_tool::env_or_default("JUDGER_INTERACTIVE", "false") == "true",
+            max_memory_mb: (max_memory_mb > 0).then_some(max_memory_mb),
+        }
+    }
+
+    pub fn supports_language(&self, language: &str) -> bool {
+        self.languages.iter().any(|name| name == language)
+    }
+
+    /// Why this judger cannot judge `problem`, if it cannot. The problem's
+    /// memory limit is in KB.
+    pub fn reject_problem(&self, problem: &Problem) -> Option<String> {
+        match self.max_memory_mb {
+            Some(max_memory_mb) if problem.memory_limit > max_memory_mb * 1024 => Some(format!(
+                "memory limit {}MB is above this judger's ceiling of {}MB",
+                problem.memory_limit.div_ceil(1024),
+                max_memory_mb
+            )),
+            _ => None,
+        }
+    }
+}
+
+pub fn routing_key(priority: JobPriority, language: &str) -> String {
+    format!("{}.{}", priority.name(), language)
+}
+
+/// Queue holding the jobs routed with `routing_key(priority, language)`.
+pub fn job_queue(priority: JobPriority, language: &str) -> String {
+    format!("{}.{}", priority.queue_name(), language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::IoMode;
+    use uuid::Uuid;
+
+    fn problem(memory_limit: u64) -> Problem {
+        Problem {
+            io_mode: IoMode::Standard,
+            input_file: None,
+            output_file: None,
+            time_limit: 1000,
+            memory_limit,
+            id: Uuid::nil(),
+            compile_flags: vec![],
+        }
+    }
+
+    fn capabilities(max_memory_mb: Option<u64>) -> Capabilities {
+        Capabilities {
+            languages: vec![],
+            interactive: false,
+            max_memory_mb,
+        }
+    }
+
+    #[test]
+    fn memory_ceiling_compares_kb_limit_with_mb() {
+        let judger = capabilities(Some(256));
+        assert!(judger.reject_problem(&problem(256 * 1024)).is_none());
+        assert_eq!(
+            judger.reject_problem(&problem(256 * 1024 + 1)).unwrap(),
+            "memory limit 257MB is above this judger's ceiling of 256MB"
+        );
+    }
+
+    #[test]
+    fn no_ceiling_accepts_every_problem() {
+        assert!(capabilities(None).reject_problem(&problem(u32::MAX as u64)).is_none());
+    }
+}
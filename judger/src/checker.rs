@@ -4,6 +4,7 @@ use std::io::{self, BufRead, BufReader};
 /// Compare two output files according to ICPC standards.
 /// - Ignores trailing whitespace on each line
 /// - Ignores differences in trailing newlines
+///
 /// Returns Ok(true) if files are equivalent, Ok(false) if different.
 pub fn check_files(path1: &str, path2: &str) -> io::Result<bool> {
     let f1 = BufReader::new(File::open(path1)?);
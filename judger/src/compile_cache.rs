@@ -0,0 +1,404 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+use crate::env_tool;
+use crate::fingerprint::Fingerprint;
+
+const ENTRY_FILE: &str = "entry.json";
+const FILES_DIR: &str = "files";
+
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+enum CachedResult {
+    /// Files the compiler wrote to the box, relative to it.
+    Compiled {
+        files: Vec<String>,
+    },
+    CompilationError {
+        log: String,
+    },
+}
+
+#[derive(Deserialize, Serialize)]
+struct CacheEntry {
+    last_used: i64,
+    size: u64,
+    result: CachedResult,
+}
+
+impl CacheEntry {
+    fn load(dir: &Path) -> Option<CacheEntry> {
+        let content = fs::read_to_string(dir.join(ENTRY_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn save(&self, dir: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tmp_path = dir.join(format!("{}.tmp", ENTRY_FILE));
+        fs::write(&tmp_path, serde_json::to_vec(self)?)?;
+        fs::rename(tmp_path, dir.join(ENTRY_FILE))?;
+        Ok(())
+    }
+}
+
+/// Files under `dir`, relative to it, except `skip`.
+fn list_files(
+    dir: &Path,
+    prefix: &Path,
+    skip: &Path,
+    files: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let relative = prefix.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            list_files(&entry.path(), &relative, skip, files)?;
+        } else if file_type.is_file() && relative != skip {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+/// Results of earlier compilations, so a rejudge or an identical resubmission
+/// does not compile the same source again. Both the compiled files and
+/// compilation errors are kept, one directory per compilation, bounded by a
+/// disk budget and evicted least-recently-used first.
+pub struct CompileCache {
+    root: PathBuf,
+    max_bytes: u64,
+}
+
+impl CompileCache {
+    /// `JUDGER_COMPILE_CACHE_MAX_MB` of 0 disables the cache.
+    pub fn from_env() -> CompileCache {
+        let root = env_tool::env_or_default(
+            "JUDGER_COMPILE_CACHE_DIR",
+            "/var/local/lib/judger/compile-cache",
+        );
+        let max_mb = env_tool::env_or_default("JUDGER_COMPILE_CACHE_MAX_MB", "512")
+            .parse::<u64>()
+            .unwrap_or(512);
+
+        CompileCache {
+            root: PathBuf::from(root),
+            max_bytes: max_mb * 1024 * 1024,
+        }
+    }
+
+    /// Key of a compilation: everything that can change what the compiler
+    /// produces. The compile command already includes the flags and file names.
+    pub fn key(
+        toolchain: Option<&str>,
+        compile_command: &[String],
+        env: &BTreeMap<String, String>,
+        source_file: &str,
+        source_code: &str,
+    ) -> String {
+        let mut fingerprint = Fingerprint::default();
+        fingerprint.field(toolchain.unwrap_or_default());
+        for arg in compile_command {
+            fingerprint.field(arg);
+        }
+        for (name, value) in env {
+            fingerprint.field(name).field(value);
+        }
+        fingerprint.field(source_file).field(source_code);
+        fingerprint.finish()
+    }
+
+    /// Copy the files of a cached compilation into `box_dir`. Returns `None` on
+    /// a miss, otherwise the compilation error log if it failed.
+    pub fn restore(&self, key: &str, box_dir: &Path) -> Option<Option<String>> {
+        if self.max_bytes == 0 {
+            return None;
+        }
+
+        let dir = self.root.join(key);
+        let mut entry = CacheEntry::load(&dir)?;
+
+        if let CachedResult::Compiled { files } = &entry.result {
+            for file in files {
+                let target = box_dir.join(file);
+                let copied = target
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|()| fs::copy(dir.join(FILES_DIR).join(file), &target));
+                if let Err(e) = copied {
+                    warn!("Dropping broken compile cache entry {}: {}", key, e);
+                    let _ = fs::remove_dir_all(&dir);
+                    return None;
+                }
+            }
+        }
+
+        entry.last_used = Utc::now().timestamp();
+        if let Err(e) = entry.save(&dir) {
+            debug!("Failed to update compile cache entry {}: {}", key, e);
+        }
+
+        Some(match entry.result {
+            CachedResult::Compiled { .. } => None,
+            CachedResult::CompilationError { log } => Some(log),
+        })
+    }
+
+    /// Remember the outcome of compiling in `box_dir`: the files it holds
+    /// besides `source_file`, or the compilation error log.
+    pub fn store(&self, key: &str, box_dir: &Path, source_file: &str, log: Option<&str>) {
+        if self.max_bytes == 0 {
+            return;
+        }
+
+        if let Err(e) = self.try_store(key, box_dir, source_file, log) {
+            warn!("Failed to cache compilation {}: {}", key, e);
+            return;
+        }
+        self.evict(key);
+    }
+
+    fn try_store(
+        &self,
+        key: &str,
+        box_dir: &Path,
+        source_file: &str,
+        log: Option<&str>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let dir = self.root.join(key);
+        // Built next to the final directory and renamed, so a half written
+        // entry is never used.
+        let tmp_dir = self.root.join(format!("{}.tmp", key));
+        let _ = fs::remove_dir_all(&tmp_dir);
+        fs::create_dir_all(tmp_dir.join(FILES_DIR))?;
+
+        let (result, size) = match log {
+            Some(log) => (
+                CachedResult::CompilationError {
+                    log: log.to_string(),
+                },
+                log.len() as u64,
+            ),
+            None => {
+                let mut files = vec![];
+                list_files(box_dir, Path::new(""), Path::new(source_file), &mut files)?;
+
+                let mut size = 0;
+                for file in &files {
+                    let target = tmp_dir.join(FILES_DIR).join(file);
+                    if let Some(parent) = target.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    size += fs::copy(box_dir.join(file), target)?;
+                }
+
+                let files = files
+                    .iter()
+                    .map(|file| file.to_string_lossy().into_owned())
+                    .collect();
+                (CachedResult::Compiled { files }, size)
+            }
+        };
+
+        CacheEntry {
+            last_used: Utc::now().timestamp(),
+            size,
+            result,
+        }
+        .save(&tmp_dir)?;
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::rename(&tmp_dir, &dir)?;
+        debug!("Cached compilation {} ({} bytes)", key, size);
+        Ok(())
+    }
+
+    /// Remove least recently used compilations until the cache fits the
+    /// budget, keeping the one just stored.
+    fn evict(&self, keep: &str) {
+        let Ok(entries) = fs::read_dir(&self.root) else {
+            return;
+        };
+
+        let mut cached: Vec<(PathBuf, i64, u64)> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter_map(|path| {
+                let entry = CacheEntry::load(&path)?;
+                Some((path, entry.last_used, entry.size))
+            })
+            .collect();
+
+        let mut total: u64 = cached.iter().map(|(_, _, size)| size).sum();
+        if total <= self.max_bytes {
+            return;
+        }
+
+        cached.sort_by_key(|(_, last_used, _)| *last_used);
+
+        for (path, _, size) in cached {
+            if total <= self.max_bytes {
+                break;
+            }
+            if path.file_name().and_then(|name| name.to_str()) == Some(keep) {
+                continue;
+            }
+
+            info!("Evicting cached compilation: {}", path.display());
+            match fs::remove_dir_all(&path) {
+                Ok(()) => total = total.saturating_sub(size),
+                Err(e) => warn!("Failed to evict {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "judger-compile-cache-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn cache(root: &Path, max_bytes: u64) -> CompileCache {
+        CompileCache {
+            root: root.to_path_buf(),
+            max_bytes,
+        }
+    }
+
+    fn command(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn key_depends_on_everything_that_changes_the_output() {
+        let compile = command(&["g++", "-O2", "main.cpp"]);
+        let env = BTreeMap::new();
+        let base = CompileCache::key(Some("g++ 13"), &compile, &env, "main.cpp", "int main(){}");
+
+        assert_eq!(
+            base,
+            CompileCache::key(Some("g++ 13"), &compile, &env, "main.cpp", "int main(){}")
+        );
+
+        let other_flags = command(&["g++", "-O0", "main.cpp"]);
+        let other_env = BTreeMap::from([("LANG".to_string(), "C".to_string())]);
+        for key in [
+            CompileCache::key(Some("g++ 14"), &compile, &env, "main.cpp", "int main(){}"),
+            CompileCache::key(None, &compile, &env, "main.cpp", "int main(){}"),
+            CompileCache::key(Some("g++ 13"), &other_flags, &env, "main.cpp", "int main(){}"),
+            CompileCache::key(Some("g++ 13"), &compile, &other_env, "main.cpp", "int main(){}"),
+            CompileCache::key(Some("g++ 13"), &compile, &env, "a.cpp", "int main(){}"),
+            CompileCache::key(Some("g++ 13"), &compile, &env, "main.cpp", "int main(){ }"),
+        ] {
+            assert_ne!(base, key);
+        }
+    }
+
+    #[test]
+    fn key_fields_do_not_run_together() {
+        let env = BTreeMap::new();
+        assert_ne!(
+            CompileCache::key(None, &command(&["g++", "-O2"]), &env, "a", ""),
+            CompileCache::key(None, &command(&["g++ -O2"]), &env, "a", "")
+        );
+    }
+
+    #[test]
+    fn compiled_files_round_trip() {
+        let root = temp_dir("compiled-root");
+        let box_dir = temp_dir("compiled-box");
+        fs::write(box_dir.join("main.cpp"), "source").unwrap();
+        fs::write(box_dir.join("main"), "binary").unwrap();
+        fs::create_dir_all(box_dir.join("classes")).unwrap();
+        fs::write(box_dir.join("classes/Main.class"), "class").unwrap();
+
+        let cache = cache(&root, 1024 * 1024);
+        assert!(cache.restore("key", &box_dir).is_none());
+        cache.store("key", &box_dir, "main.cpp", None);
+
+        let restored = temp_dir("compiled-restored");
+        assert_eq!(cache.restore("key", &restored), Some(None));
+        assert_eq!(fs::read_to_string(restored.join("main")).unwrap(), "binary");
+        assert_eq!(
+            fs::read_to_string(restored.join("classes/Main.class")).unwrap(),
+            "class"
+        );
+        assert!(!restored.join("main.cpp").exists());
+
+        for dir in [root, box_dir, restored] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn compilation_error_round_trips() {
+        let root = temp_dir("error-root");
+        let box_dir = temp_dir("error-box");
+
+        let cache = cache(&root, 1024 * 1024);
+        cache.store("key", &box_dir, "main.cpp", Some("main.cpp:1: error"));
+        assert_eq!(
+            cache.restore("key", &box_dir),
+            Some(Some("main.cpp:1: error".to_string()))
+        );
+
+        for dir in [root, box_dir] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn disabled_cache_stores_nothing() {
+        let root = temp_dir("disabled-root");
+        let box_dir = temp_dir("disabled-box");
+
+        let cache = cache(&root, 0);
+        cache.store("key", &box_dir, "main.cpp", Some("error"));
+        assert!(cache.restore("key", &box_dir).is_none());
+
+        for dir in [root, box_dir] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn eviction_removes_least_recently_used_but_keeps_the_new_entry() {
+        let root = temp_dir("evict-root");
+        let box_dir = temp_dir("evict-box");
+
+        let unbounded = cache(&root, 1024 * 1024);
+        for (key, last_used) in [("old", 1), ("middle", 2), ("new", 3)] {
+            unbounded.store(key, &box_dir, "main.cpp", Some(&"x".repeat(100)));
+            let dir = root.join(key);
+            let mut entry = CacheEntry::load(&dir).unwrap();
+            entry.last_used = last_used;
+            entry.save(&dir).unwrap();
+        }
+
+        // Over budget by one entry: the oldest is the one being kept, so the
+        // next oldest goes instead.
+        cache(&root, 250).evict("old");
+        assert!(root.join("old").exists());
+        assert!(!root.join("middle").exists());
+        assert!(root.join("new").exists());
+
+        for dir in [root, box_dir] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use aws_sdk_s3::Client;
+use sqlx::PgPool;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cache::TestDataCache;
+use crate::compile_cache::CompileCache;
+use crate::cancel::Cancellations;
+use crate::capabilities::Capabilities;
+use crate::db::make_db_pool;
+use crate::env_tool;
+use crate::languages::LanguageTable;
+use crate::minio::make_minio_client;
+use crate::outbox::{FinishedJobs, outbox_dir_from_env};
+use crate::status::JudgerStatus;
+
+pub struct Config {
+    pub judger_id: String,
+    pub prefetch_concurrency: usize,
+    pub shutdown_grace: Duration,
+    pub max_delivery_attempts: u32,
+    pub max_buffered_messages: usize,
+    pub capabilities: Capabilities,
+}
+
+impl Config {
+    pub fn from_env(languages: &LanguageTable) -> Result<Config, Box<dyn Error + Send + Sync>> {
+        Ok(Config {
+            judger_id: env_tool::var("JUDGER_ID")?,
+            prefetch_concurrency: env_tool::env_or_default("JUDGER_PREFETCH_CONCURRENCY", "4")
+                .parse::<usize>()
+                .unwrap_or(4)
+                .max(1),
+            shutdown_grace: Duration::from_secs(
+                env_tool::env_or_default("JUDGER_SHUTDOWN_GRACE_SECS", "20")
+                    .parse::<u64>()
+                    .unwrap_or(20),
+            ),
+            max_delivery_attempts: env_tool::env_or_default("JUDGER_MAX_DELIVERY_ATTEMPTS", "3")
+                .parse::<u32>()
+                .unwrap_or(3)
+                .max(1),
+            max_buffered_messages: env_tool::env_or_default("JUDGER_MAX_BUFFERED_MESSAGES", "1000")
+                .parse::<usize>()
+                .unwrap_or(1000)
+                .max(1),
+            capabilities: Capabilities::from_env(languages.names()),
+        })
+    }
+}
+
+/// Long-lived resources shared by every job handled by this judger. Created
+/// once in `main` so connections to Postgres and MinIO are reused.
+pub struct AppContext {
+    pub config: Config,
+    pub languages: Arc<LanguageTable>,
+    pub db: PgPool,
+    pub s3: Client,
+    pub cache: TestDataCache,
+    pub compile_cache: CompileCache,
+    pub cancellations: Arc<Cancellations>,
+    pub status: JudgerStatus,
+    pub finished_jobs: FinishedJobs,
+}
+
+impl AppContext {
+    pub fn new() -> Result<AppContext, Box<dyn Error + Send + Sync>> {
+        let languages = Arc::new(LanguageTable::from_env()?);
+
+        Ok(AppContext {
+            config: Config::from_env(&languages)?,
+            languages,
+            db: make_db_pool()?,
+            s3: make_minio_client(),
+            cache: TestDataCache::from_env(),
+            compile_cache: CompileCache::from_env(),
+            cancellations: Arc::new(Cancellations::default()),
+            status: JudgerStatus::new(),
+            finished_jobs: FinishedJobs::open(&outbox_dir_from_env())?,
+        })
+    }
+}
@@ -1,3 +1,7 @@
+use sqlx::PgPool;
+use sqlx::postgres::PgPoolOptions;
+use std::time::Duration;
+
 use crate::env_tool;
 
 pub fn get_db_url() -> String {
@@ -6,8 +10,21 @@ pub fn get_db_url() -> String {
     let db_user = env_tool::env_or_default("DB_USER", "postgres");
     let db_pass = env_tool::env_or_default("DB_PASS", "postgres");
     let db_name = env_tool::env_or_default("DB_NAME", "postgres");
-    return format!(
+    format!(
         "postgres://{}:{}@{}:{}/{}",
         db_user, db_pass, db_host, db_port, db_name
-    );
+    )
+}
+
+pub fn make_db_pool() -> Result<PgPool, sqlx::Error> {
+    let max_connections = env_tool::env_or_default("DB_MAX_CONNECTIONS", "2")
+        .parse::<u32>()
+        .unwrap_or(2);
+
+    PgPoolOptions::new()
+        .max_connections(max_connections)
+        .min_connections(0)
+        .acquire_timeout(Duration::from_secs(10))
+        .idle_timeout(Duration::from_secs(300))
+        .connect_lazy(&get_db_url())
 }
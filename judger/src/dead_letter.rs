@@ -0,0 +1,156 @@
+use lapin::{
+    BasicProperties, Channel, ExchangeKind,
+    message::Delivery,
+    options::*,
+    types::{AMQPValue, FieldTable},
+};
+use std::error::Error;
+use tracing::{error, info};
+
+pub const DEAD_LETTER_EXCHANGE: &str = "judger.dlx";
+pub const DEAD_LETTER_QUEUE: &str = "judger.job.dlq";
+
+/// Header counting how many times a job was handed out without being finished.
+const ATTEMPTS_HEADER: &str = "x-judger-attempts";
+/// Header carrying the reason a message was dead-lettered.
+const ERROR_HEADER: &str = "x-judger-error";
+
+pub async fn declare_dead_letter_queue(channel: &Channel) -> Result<(), lapin::Error> {
+    channel
+        .exchange_declare(
+            DEAD_LETTER_EXCHANGE,
+            ExchangeKind::Fanout,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..ExchangeDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    channel
+        .queue_declare(
+            DEAD_LETTER_QUEUE,
+            QueueDeclareOptions {
+                durable: true,
+                ..QueueDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    channel
+        .queue_bind(
+            DEAD_LETTER_QUEUE,
+            DEAD_LETTER_EXCHANGE,
+            "",
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Number of previous attempts at this job that never finished because the
+/// judger crashed. Jobs handed back on shutdown keep their count.
+pub fn delivery_attempts(delivery: &Delivery) -> u32 {
+    let recorded = delivery
+        .properties
+        .headers()
+        .as_ref()
+        .and_then(|headers| headers.inner().get(ATTEMPTS_HEADER))
+        .and_then(|value| {
+            value
+                .as_long_long_int()
+                .or_else(|| value.as_long_int().map(i64::from))
+                .or_else(|| value.as_long_uint().map(i64::from))
+        })
+        .unwrap_or(0) as u32;
+
+    if delivery.redelivered {
+        recorded + 1
+    } else {
+        recorded
+    }
+}
+
+fn with_header(delivery: &Delivery, key: &str, value: AMQPValue) -> BasicProperties {
+    let mut headers = delivery.properties.headers().clone().unwrap_or_default();
+    headers.insert(key.into(), value);
+    delivery.properties.clone().with_headers(headers)
+}
+
+/// Publish a copy of `delivery` and wait for RabbitMQ to confirm it, so the
+/// original is only settled once the copy is safe.
+pub async fn republish(
+    channel: &Channel,
+    exchange: &str,
+    routing_key: &str,
+    delivery: &Delivery,
+    properties: BasicProperties,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let confirm = channel
+        .basic_publish(
+            exchange,
+            routing_key,
+            BasicPublishOptions::default(),
+            &delivery.data,
+            properties,
+        )
+        .await?;
+
+    if !confirm.await?.is_ack() {
+        return Err("RabbitMQ did not confirm the message".into());
+    }
+
+    Ok(())
+}
+
+/// Put the message back at the end of its queue with the attempt count
+/// recorded in its headers, publishing it the same way it was published
+/// originally. The caller acks the original delivery once this succeeds.
+pub async fn requeue_with_attempts(
+    channel: &Channel,
+    delivery: &Delivery,
+    attempts: u32,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    info!(
+        "Requeueing message from {} (attempt {})",
+        delivery.routing_key, attempts
+    );
+
+    republish(
+        channel,
+        delivery.exchange.as_str(),
+        delivery.routing_key.as_str(),
+        delivery,
+        with_header(
+            delivery,
+            ATTEMPTS_HEADER,
+            AMQPValue::LongLongInt(attempts as i64),
+        ),
+    )
+    .await
+}
+
+/// Move a message that cannot be handled to the dead-letter queue, with the
+/// reason attached. The caller acks the original delivery once this
+/// succeeds, and requeues it otherwise so the message is not lost.
+pub async fn dead_letter(
+    channel: &Channel,
+    delivery: &Delivery,
+    reason: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    error!("Dead-lettering message: {}", reason);
+
+    republish(
+        channel,
+        DEAD_LETTER_EXCHANGE,
+        delivery.routing_key.as_str(),
+        delivery,
+        with_header(delivery, ERROR_HEADER, AMQPValue::LongString(reason.into())),
+    )
+    .await
+    .inspect_err(|e| error!("Failed to publish to dead-letter exchange: {:?}", e))
+}
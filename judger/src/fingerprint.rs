@@ -0,0 +1,19 @@
+use sha2::{Digest, Sha256};
+
+/// Hash of a sequence of fields, each prefixed with its length so that
+/// different splits of the same bytes never collide.
+#[derive(Default)]
+pub struct Fingerprint(Sha256);
+
+impl Fingerprint {
+    pub fn field(&mut self, value: impl AsRef<[u8]>) -> &mut Fingerprint {
+        let value = value.as_ref();
+        self.0.update((value.len() as u64).to_le_bytes());
+        self.0.update(value);
+        self
+    }
+
+    pub fn finish(self) -> String {
+        format!("{:x}", self.0.finalize())
+    }
+}
@@ -0,0 +1,235 @@
+/// A token of Java source: an identifier or keyword, or a single symbol.
+#[derive(PartialEq, Eq)]
+enum Token<'a> {
+    Word(&'a str),
+    Symbol(char),
+}
+
+/// Split Java source into words and symbols, skipping whitespace, comments,
+/// string and character literals, text blocks included.
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '/' if chars.peek().map(|(_, next)| *next) == Some('/') => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek().map(|(_, next)| *next) == Some('*') => {
+                chars.next();
+                let mut previous = ' ';
+                for (_, c) in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            '"' if source[start..].starts_with("\"\"\"") => {
+                chars.nth(1);
+                let mut quotes = 0;
+                let mut escaped = false;
+                for (_, next) in chars.by_ref() {
+                    if escaped {
+                        escaped = false;
+                    } else if next == '\\' {
+                        escaped = true;
+                    } else if next == '"' {
+                        quotes += 1;
+                        if quotes == 3 {
+                            break;
+                        }
+                        continue;
+                    }
+                    quotes = 0;
+                }
+            }
+            '"' | '\'' => {
+                let mut escaped = false;
+                for (_, next) in chars.by_ref() {
+                    if escaped {
+                        escaped = false;
+                    } else if next == '\\' {
+                        escaped = true;
+                    } else if next == c {
+                        break;
+                    }
+                }
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '$' => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(index, next)) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '_' || next == '$') {
+                        break;
+                    }
+                    end = index + next.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token::Word(&source[start..end]));
+            }
+            c => tokens.push(Token::Symbol(c)),
+        }
+    }
+
+    tokens
+}
+
+const TYPE_KEYWORDS: [&str; 4] = ["class", "interface", "enum", "record"];
+
+/// Find the class a Java submission has to be saved and run as: its public
+/// top-level type, or else the top-level type declaring `static void main`.
+pub fn find_main_class(source: &str) -> Option<String> {
+    let tokens = tokenize(source);
+
+    let mut depth = 0usize;
+    // Top-level type currently open, with whether it was declared public.
+    let mut current: Option<(&str, bool)> = None;
+    let mut pending: Option<(&str, bool)> = None;
+    let mut public_class = None;
+    let mut main_class = None;
+
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Symbol('{') => {
+                if depth == 0 {
+                    current = pending.take();
+                }
+                depth += 1;
+            }
+            Token::Symbol('}') => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    current = None;
+                }
+            }
+            Token::Word(word) if depth == 0 && TYPE_KEYWORDS.contains(word) => {
+                if let Some(Token::Word(name)) = tokens.get(index + 1) {
+                    let is_public = tokens[..index]
+                        .iter()
+                        .rev()
+                        .take_while(|token| matches!(token, Token::Word(_)))
+                        .any(|token| *token == Token::Word("public"));
+                    pending = Some((name, is_public));
+                    if is_public && public_class.is_none() {
+                        public_class = Some(name.to_string());
+                    }
+                }
+            }
+            Token::Word("main") if depth > 0 && main_class.is_none() => {
+                let is_main_method = tokens.get(index + 1) == Some(&Token::Symbol('('))
+                    && index >= 1
+                    && tokens[index - 1] == Token::Word("void")
+                    && tokens[..index - 1]
+                        .iter()
+                        .rev()
+                        .take_while(|token| matches!(token, Token::Word(_)))
+                        .any(|token| *token == Token::Word("static"));
+                if is_main_method && let Some((name, _)) = current {
+                    main_class = Some(name.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    public_class.or(main_class)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn public_class_is_the_main_class() {
+        let source = r#"
+            import java.util.*;
+
+            class Helper {
+                static void main(String[] args) {}
+            }
+
+            public class Solution {
+                public static void main(String[] args) {
+                    System.out.println(new Scanner(System.in).nextInt());
+                }
+            }
+        "#;
+        assert_eq!(find_main_class(source).as_deref(), Some("Solution"));
+    }
+
+    #[test]
+    fn public_class_may_have_other_modifiers() {
+        let source = "public final class Solution { public static void main(String[] a) {} }";
+        assert_eq!(find_main_class(source).as_deref(), Some("Solution"));
+        let source = "final public class Solution {}";
+        assert_eq!(find_main_class(source).as_deref(), Some("Solution"));
+    }
+
+    #[test]
+    fn main_method_in_a_non_public_class() {
+        let source = r#"
+            interface Solver { int solve(); }
+
+            class Program {
+                static class Inner {
+                    void main() {}
+                }
+
+                public static void main(String[] args) {}
+            }
+        "#;
+        assert_eq!(find_main_class(source).as_deref(), Some("Program"));
+    }
+
+    #[test]
+    fn braces_and_class_in_comments_and_literals_are_ignored() {
+        let source = r#"
+            // public class Commented {
+            /* public class Block { } */
+            class Main {
+                static String text = "public class Quoted { } {";
+                static char open = '{';
+                static char quote = '\'';
+                static String escaped = "\" } class Escaped {";
+                static String block = """
+                    public class TextBlock {
+                        "quoted" } \""" still inside ""
+                    """;
+
+                public static void main(String[] args) {}
+            }
+        "#;
+        assert_eq!(find_main_class(source).as_deref(), Some("Main"));
+    }
+
+    #[test]
+    fn types_after_a_text_block_are_found() {
+        let source = r#"
+            class Data {
+                static String block = """
+                    {{"nested": "json"}}""";
+            }
+
+            public class Answer {}
+        "#;
+        assert_eq!(find_main_class(source).as_deref(), Some("Answer"));
+    }
+
+    #[test]
+    fn no_entry_point() {
+        for source in [
+            "",
+            "class Main { void main(String[] args) {} }",
+            "class Main { static int main; }",
+            "interface Runnable { void run(); }",
+        ] {
+            assert_eq!(find_main_class(source), None, "{}", source);
+        }
+    }
+}
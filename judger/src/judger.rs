@@ -1,38 +1,49 @@
-use sqlx;
-use sqlx::Row;
+use sqlx::{PgPool, Row};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
 use std::fs::File;
 use std::io::Write;
-use std::process::Command;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
 use tracing::{error, info, debug};
 use uuid::Uuid;
 
+use aws_sdk_s3::Client;
+
+use crate::cache::ProblemCache;
+use crate::compile_cache::CompileCache;
+use crate::cancel::CancelToken;
+use crate::context::AppContext;
 use crate::env_tool;
-use crate::db::get_db_url;
-use crate::languages::get_language_config;
 use crate::metadata::{get_f64, get_u64, metadata_file_to_hashmap};
-use crate::minio::{make_minio_client, minio_object_to_string};
+use crate::natural_sort::natural_cmp;
+use crate::prefetch::Prefetcher;
 use crate::models::{
-    IoMode, JudgerJob, JudgerResult, LanguageConfig, Problem, ResultStatus, Status, TestCase,
-    TestResult,
+    IoMode, LanguageConfig, Problem, SubmissionCommands, TestCaseRef, is_allowed_problem_flag,
+};
+use crate::protocol::{
+    JudgerJob, JudgerResult, PreviousTestResult, RejudgeJob, RejudgeResult, ResultStatus, RunJob,
+    RunResult, Status, TestResult, TestResultChange,
 };
 use crate::checker::check_files;
 
-async fn get_problem(problem_id: &Uuid) -> Result<Problem, Box<dyn Error>> {
+/// Maximum number of bytes of stdout/stderr returned by a custom-input run.
+const RUN_OUTPUT_LIMIT: usize = 64 * 1024;
+
+pub async fn get_problem(pool: &PgPool, problem_id: &Uuid) -> Result<Problem, Box<dyn Error + Send + Sync>> {
     info!("Fetching problem with id: {}", problem_id);
-    let pool = sqlx::PgPool::connect(&get_db_url()).await?;
 
     let row = sqlx::query(
         r#"
-        SELECT "ioMode", "inputFile", "outputFile", "timeLimit", "memoryLimit", "id"
+        SELECT "ioMode", "inputFile", "outputFile", "timeLimit", "memoryLimit", "id", "compileFlags"
         FROM problems
         WHERE id = $1::uuid
         "#,
     )
     .bind(problem_id)
-    .fetch_one(&pool)
+    .fetch_one(pool)
     .await?;
 
     let problem = Problem {
@@ -42,66 +53,77 @@ async fn get_problem(problem_id: &Uuid) -> Result<Problem, Box<dyn Error>> {
         time_limit: row.try_get::<i32, _>("timeLimit")? as u64,
         memory_limit: row.try_get::<i32, _>("memoryLimit")? as u64,
         id: row.try_get::<Uuid, _>("id")?,
+        compile_flags: row
+            .try_get::<String, _>("compileFlags")?
+            .split_whitespace()
+            .map(str::to_string)
+            .collect(),
     };
 
+    if let Some(flag) = problem
+        .compile_flags
+        .iter()
+        .find(|flag| !is_allowed_problem_flag(flag))
+    {
+        return Err(format!("Problem {} has a disallowed compile flag {:?}", problem_id, flag).into());
+    }
+
     info!("Successfully fetched problem: {}", problem_id);
     Ok(problem)
 }
 
-async fn get_test_cases(problem_id: &Uuid) -> Result<Vec<String>, sqlx::Error> {
+async fn get_test_cases(pool: &PgPool, problem_id: &Uuid) -> Result<Vec<String>, sqlx::Error> {
     info!("Fetching test cases for problem: {}", problem_id);
-    let pool = sqlx::PgPool::connect(&get_db_url()).await?;
 
     let rows = sqlx::query(
         r#"
-        SELECT subtasks.slug || '/' || test_cases.slug AS merge
+        SELECT subtasks.slug AS subtask_slug,
+               subtasks.position AS subtask_position,
+               test_cases.slug AS test_case_slug,
+               test_cases.position AS test_case_position
         FROM subtasks
         INNER JOIN test_cases
             ON test_cases."subtaskId" = subtasks.id
         WHERE subtasks."problemId" = $1::uuid
+        ORDER BY subtasks.position, test_cases.position
         "#,
     )
     .bind(problem_id)
-    .fetch_all(&pool)
+    .fetch_all(pool)
     .await?;
 
-    let test_cases: Vec<String> = rows
+    let mut test_cases: Vec<TestCaseRef> = rows
         .into_iter()
-        .map(|row| row.get::<String, _>("merge"))
+        .map(|row| TestCaseRef {
+            subtask_slug: row.get::<String, _>("subtask_slug"),
+            subtask_position: row.get::<i32, _>("subtask_position"),
+            slug: row.get::<String, _>("test_case_slug"),
+            position: row.get::<i32, _>("test_case_position"),
+        })
         .collect();
 
+    // Positions default to 0, so ties are broken by natural order of the slugs
+    // to keep the order stable across rejudges.
+    test_cases.sort_by(|a, b| {
+        a.subtask_position
+            .cmp(&b.subtask_position)
+            .then_with(|| natural_cmp(&a.subtask_slug, &b.subtask_slug))
+            .then_with(|| a.position.cmp(&b.position))
+            .then_with(|| natural_cmp(&a.slug, &b.slug))
+    });
+
+    let test_cases: Vec<String> = test_cases.iter().map(TestCaseRef::full_slug).collect();
+
     info!("Found {} test cases for problem: {}", test_cases.len(), problem_id);
     Ok(test_cases)
 }
 
-async fn get_test_case_content(problem_id: &Uuid, slug: &str) -> Result<TestCase, Box<dyn Error>> {
-    info!("Fetching test case content for {}/{}", problem_id, slug);
-    let minio_client = make_minio_client();
-
-    let input_content = minio_client
-        .get_object()
-        .bucket("test-cases")
-        .key(format!("{}/{}/{}", problem_id, slug, "input"))
-        .send()
-        .await?;
-
-    let output_content = minio_client
-        .get_object()
-        .bucket("test-cases")
-        .key(format!("{}/{}/{}", problem_id, slug, "output"))
-        .send()
-        .await?;
-
-    let test_case = TestCase {
-        input: minio_object_to_string(input_content).await?,
-        output: minio_object_to_string(output_content).await?,
-    };
-    
-    debug!("Successfully fetched test case content for {}/{}", problem_id, slug);
-    Ok(test_case)
-}
-
-async fn write_test_case_input(slug: &str, problem: &Problem) -> Result<(), Box<dyn Error>> {
+async fn write_test_case_input(
+    slug: &str,
+    problem: &Problem,
+    test_data: &ProblemCache,
+    minio_client: &Client,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
     debug!("Writing test case input for: {}", slug);
     let input_file_name = if problem.io_mode == IoMode::Standard {
         "input.txt"
@@ -109,21 +131,27 @@ async fn write_test_case_input(slug: &str, problem: &Problem) -> Result<(), Box<
         problem.input_file.as_ref().unwrap()
     };
 
-    let test_case_content = get_test_case_content(&problem.id, slug).await?;
-
-    let mut input_file = File::create(format!(
-        "/var/local/lib/isolate/{}/box/{}",
-        env_tool::var("JUDGER_ID").unwrap(),
-        input_file_name
-    ))?;
+    let cached_input = test_data.fetch(minio_client, slug, "input").await?;
 
-    input_file.write_all(test_case_content.input.as_bytes())?;
+    fs::copy(
+        cached_input,
+        format!(
+            "/var/local/lib/isolate/{}/box/{}",
+            env_tool::var("JUDGER_ID").unwrap(),
+            input_file_name
+        ),
+    )?;
 
     debug!("Test case input written successfully for: {}", slug);
     Ok(())
 }
 
-async fn write_test_case_answer(slug: &str, problem: &Problem) -> Result<(), Box<dyn Error>> {
+async fn write_test_case_answer(
+    slug: &str,
+    problem: &Problem,
+    test_data: &ProblemCache,
+    minio_client: &Client,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
     debug!("Writing test case answer for: {}", slug);
     let output_file_name = if problem.io_mode == IoMode::Standard {
         "output.txt"
@@ -131,21 +159,22 @@ async fn write_test_case_answer(slug: &str, problem: &Problem) -> Result<(), Box
         problem.output_file.as_ref().unwrap()
     };
 
-    let test_case_content = get_test_case_content(&problem.id, slug).await?;
+    let cached_output = test_data.fetch(minio_client, slug, "output").await?;
 
-    let mut output_file = File::create(format!(
-        "/var/local/lib/isolate/{}/box/{}.ans",
-        env_tool::var("JUDGER_ID").unwrap(),
-        output_file_name
-    ))?;
-
-    output_file.write_all(test_case_content.output.as_bytes())?;
+    fs::copy(
+        cached_output,
+        format!(
+            "/var/local/lib/isolate/{}/box/{}.ans",
+            env_tool::var("JUDGER_ID").unwrap(),
+            output_file_name
+        ),
+    )?;
 
     debug!("Test case answer written successfully for: {}", slug);
     Ok(())
 }
 
-fn create_isolate_box() -> Result<(), Box<dyn Error>> {
+fn create_isolate_box() -> Result<(), Box<dyn Error + Send + Sync>> {
     info!("Creating isolate box with ID: {}", env_tool::var("JUDGER_ID").unwrap());
     let create_box_res = Command::new("isolate")
         .arg("--init")
@@ -164,12 +193,36 @@ fn create_isolate_box() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn write_source_code(source_code: &str, ext: &str) -> Result<(), Box<dyn Error>> {
-    info!("Writing source code with extension: {}", ext);
+pub fn cleanup_isolate_box() -> Result<(), Box<dyn Error + Send + Sync>> {
+    info!("Cleaning up isolate box with ID: {}", env_tool::var("JUDGER_ID").unwrap());
+    let cleanup_box_res = Command::new("isolate")
+        .arg("--cleanup")
+        .arg(format!("--box-id={}", env_tool::var("JUDGER_ID").unwrap()))
+        .output()?;
+
+    if !cleanup_box_res.status.success() {
+        error!(
+            "Failed to clean up isolate box: {}",
+            String::from_utf8_lossy(&cleanup_box_res.stderr).to_string()
+        );
+        return Err("Failed to clean up isolate box".into());
+    }
+
+    info!("Isolate box cleaned up successfully");
+    Ok(())
+}
+
+/// The box directory of this judger's sandbox, outside the sandbox.
+fn box_dir() -> String {
+    format!("/var/local/lib/isolate/{}/box", env_tool::var("JUDGER_ID").unwrap())
+}
+
+fn write_source_code(source_code: &str, file_name: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    info!("Writing source code to: {}", file_name);
     let mut source_file = File::create(format!(
-        "/var/local/lib/isolate/{}/box/main.{}",
+        "/var/local/lib/isolate/{}/box/{}",
         env_tool::var("JUDGER_ID").unwrap(),
-        ext
+        file_name
     ))?;
 
     source_file.write_all(source_code.as_bytes())?;
@@ -178,119 +231,291 @@ fn write_source_code(source_code: &str, ext: &str) -> Result<(), Box<dyn Error>>
     Ok(())
 }
 
-fn compile_source_code(job_id: &Uuid, compile_command: &str) -> Result<(), JudgerResult> {
-    info!("Compiling source code with command: {}", compile_command);
-    let args = compile_command.split_whitespace().collect::<Vec<&str>>();
+/// A submission that did not compile.
+struct CompileError {
+    log: String,
+    /// Whether the compiler exited on its own with a status code. A compiler
+    /// killed by a signal, such as by the OOM killer, says nothing about the
+    /// submission, so its failure is not cached.
+    exited: bool,
+}
 
-    let output = Command::new(args[0])
-        .current_dir(format!(
-            "/var/local/lib/isolate/{}/box",
-            env_tool::var("JUDGER_ID").unwrap()
-        ))
-        .args(&args[1..])
+/// Compile the source code inside the box. Returns the compilation error if
+/// the submission does not compile. Failing to start the compiler is an error
+/// of the judger, not of the submission. The compiler is killed if the
+/// returned future is dropped, such as when a shutdown stops waiting for the
+/// job.
+async fn compile_source_code(
+    compile_command: &[String],
+    language_config: &LanguageConfig,
+) -> Result<Option<CompileError>, Box<dyn Error + Send + Sync>> {
+    info!("Compiling source code with command: {:?}", compile_command);
+
+    let output = tokio::process::Command::new(&compile_command[0])
+        .current_dir(box_dir())
+        .args(&compile_command[1..])
+        .envs(&language_config.env)
+        .kill_on_drop(true)
         .output()
-        .map_err(|e| JudgerResult {
-            id: job_id.clone(),
-            log: e.to_string(),
-            status: ResultStatus::CE,
-            test_results: vec![],
-        })?;
+        .await
+        .map_err(|e| format!("Failed to start the compiler {}: {}", compile_command[0], e))?;
 
     if !output.status.success() {
         error!(
-            "Failed to compile source code: {}",
+            "Failed to compile source code ({}): {}",
+            output.status,
             String::from_utf8_lossy(&output.stderr).to_string()
         );
-        return Err(JudgerResult {
-            id: job_id.clone(),
+        return Ok(Some(CompileError {
             log: String::from_utf8_lossy(&output.stderr).to_string(),
-            status: ResultStatus::CE,
-            test_results: vec![],
-        });
+            exited: output.status.code().is_some(),
+        }));
     }
 
     info!("Source code compiled successfully");
-    Ok(())
+    Ok(None)
 }
 
-fn run_testcase(language_config: &LanguageConfig, problem: &Problem) -> Result<(), Box<dyn Error>> {
+/// Compile the source code, reusing the result of an identical earlier
+/// compilation if there is one. Returns the compilation error log if the
+/// submission does not compile. Cached compilation errors are only reused
+/// when `reuse_errors` is set, so a rejudge always compiles again after one.
+async fn compile_with_cache(
+    ctx: &AppContext,
+    commands: &SubmissionCommands,
+    language_config: &LanguageConfig,
+    toolchain: Option<&str>,
+    source_code: &str,
+    reuse_errors: bool,
+) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    let key = CompileCache::key(
+        toolchain,
+        &commands.compile_command,
+        &language_config.env,
+        &commands.source_file,
+        source_code,
+    );
+    let box_dir = PathBuf::from(box_dir());
+
+    match ctx.compile_cache.restore(&key, &box_dir) {
+        Some(None) => {
+            info!("Reusing cached compilation {}", key);
+            return Ok(None);
+        }
+        Some(Some(log)) if reuse_errors => {
+            info!("Reusing cached compilation error {}", key);
+            return Ok(Some(log));
+        }
+        _ => {}
+    }
+
+    let error = compile_source_code(&commands.compile_command, language_config).await?;
+    match &error {
+        None => ctx
+            .compile_cache
+            .store(&key, &box_dir, &commands.source_file, None),
+        Some(error) if error.exited => ctx
+            .compile_cache
+            .store(&key, &box_dir, &commands.source_file, Some(&error.log)),
+        Some(_) => {}
+    }
+    Ok(error.map(|error| error.log))
+}
+
+/// Time and memory limits of the syntax check, independent of the problem.
+const CHECK_TIME_LIMIT_SECS: u64 = 10;
+const CHECK_MEMORY_LIMIT_KB: u64 = 256 * 1024;
+
+/// Run the syntax check of an interpreted language in the sandbox. Returns the
+/// interpreter output as the compilation error log if the check fails.
+async fn check_source_code(
+    check_command: &[String],
+    language_config: &LanguageConfig,
+) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    info!("Checking source code with command: {:?}", check_command);
+
+    let mut cmd = tokio::process::Command::new("isolate");
+    cmd.arg("--run")
+        .arg(format!("--box-id={}", env_tool::var("JUDGER_ID").unwrap()))
+        .arg(format!("--time={}", CHECK_TIME_LIMIT_SECS))
+        .arg(format!("--wall-time={}", CHECK_TIME_LIMIT_SECS * 2))
+        .arg(format!(
+            "--mem={}",
+            CHECK_MEMORY_LIMIT_KB + language_config.runtime_overhead_mb * 1024
+        ))
+        .arg(format!("--processes={}", language_config.processes))
+        .arg("--stdout=check.txt")
+        .arg("--stderr-to-stdout");
+
+    for (name, value) in &language_config.env {
+        cmd.arg(format!("--env={}={}", name, value));
+    }
+
+    let output = cmd
+        .arg("--")
+        .args(check_command)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to start isolate: {}", e))?;
+
+    if !output.status.success() {
+        let log = read_box_file_truncated("check.txt");
+        error!("Source code failed the syntax check: {}", log);
+        return Ok(Some(if log.is_empty() {
+            String::from_utf8_lossy(&output.stderr).to_string()
+        } else {
+            log
+        }));
+    }
+
+    info!("Source code passed the syntax check");
+    Ok(None)
+}
+
+/// Whether a sandboxed run finished or was killed because its job was cancelled.
+enum RunOutcome {
+    Finished,
+    Cancelled,
+}
+
+/// Run a step of `job_id` unless the job is cancelled first, in which case the
+/// step is dropped, killing the process it started, and `None` is returned.
+async fn unless_cancelled<T>(
+    cancel: &CancelToken<'_>,
+    job_id: &Uuid,
+    step: impl Future<Output = T>,
+) -> Option<T> {
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => {
+            info!("Job {} was cancelled, stopping it", job_id);
+            None
+        }
+        output = step => Some(output),
+    }
+}
+
+async fn run_testcase(
+    language_config: &LanguageConfig,
+    run_command: &[String],
+    problem: &Problem,
+    cancel: &CancelToken<'_>,
+    job_id: &Uuid,
+) -> Result<RunOutcome, Box<dyn Error + Send + Sync>> {
     info!("Running testcase with time limit: {}ms, memory limit: {}KB", 
           problem.time_limit, problem.memory_limit);
           
     let arg_box_id = format!("--box-id={}", env_tool::var("JUDGER_ID").unwrap());
     let arg_time = format!("--time={}", (problem.time_limit as f64) / 1000.0);
     let arg_wall_time = format!("--wall-time={}", (problem.time_limit as f64) / 1000.0 + 1.0);
-    let arg_mem = format!("--mem={}", problem.memory_limit);
+    let arg_mem = format!(
+        "--mem={}",
+        problem.memory_limit + language_config.runtime_overhead_mb * 1024
+    );
+    let arg_processes = format!("--processes={}", language_config.processes);
     let arg_fsize = format!("--fsize={}", 131072);
     let arg_meta = format!(
         "--meta=/var/local/lib/isolate/{}/box/meta.txt",
         env_tool::var("JUDGER_ID").unwrap()
     );
 
-    let mut cmd = Command::new("isolate");
+    let mut cmd = tokio::process::Command::new("isolate");
     cmd.arg("--run")
         .arg(&arg_box_id)
         .arg(&arg_time)
         .arg(&arg_wall_time)
         .arg(&arg_mem)
+        .arg(&arg_processes)
         .arg(&arg_fsize)
         .arg(&arg_meta);
 
     if problem.io_mode == IoMode::Standard {
-        let arg_input = format!("--stdin=input.txt");
-        let arg_output = format!("--stdout=output.txt");
+        cmd.arg("--stdin=input.txt").arg("--stdout=output.txt");
+    }
+    cmd.arg("--stderr=stderr.txt");
 
-        cmd.arg(&arg_input).arg(&arg_output);
+    for (name, value) in &language_config.env {
+        cmd.arg(format!("--env={}={}", name, value));
     }
 
-    let args = language_config
-        .run_command
-        .split_whitespace()
-        .collect::<Vec<&str>>();
+    let mut child = cmd
+        .arg("--")
+        .args(run_command)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
 
-    let _output = cmd.args(&args).output()?; // Prefix with underscore to mark as intentionally unused
+    tokio::select! {
+        status = child.wait() => {
+            status?;
+        }
+        _ = cancel.cancelled() => {
+            info!("Job {} was cancelled, killing the sandbox", job_id);
+            child.kill().await?;
+            cleanup_isolate_box()?;
+            return Ok(RunOutcome::Cancelled);
+        }
+    }
 
     debug!("Testcase run completed");
-    Ok(())
+    Ok(RunOutcome::Finished)
 }
 
-fn check_result(problem: &Problem, test_case_slug: &str) -> Result<TestResult, Box<dyn Error>> {
-    info!("Checking result for test case: {}", test_case_slug);
-    
-    fn is_memory_limit_exceeded(
-        metadata: &HashMap<String, String>,
-        memory_limit_kb: usize,
-    ) -> bool {
-        // Trường hợp chết bởi SIGSEGV (exitsig == "11")
-        if metadata.get("exitsig").map(|v| v == "11").unwrap_or(false) {
-            let max_rss = get_u64(metadata, "max-rss");
-
-            if max_rss as usize > (memory_limit_kb as f64 * 0.95) as usize {
-                return true;
-            }
-        }
+pub fn is_memory_limit_exceeded(
+    metadata: &HashMap<String, String>,
+    memory_limit_kb: usize,
+) -> bool {
+    // Runtimes with an overhead allowance can go over the limit without the
+    // sandbox stopping them. Their heap is capped below the limit by
+    // `runtime_baseline_mb`, so this only catches memory used beyond it.
+    if get_u64(metadata, "max-rss") as usize > memory_limit_kb {
+        return true;
+    }
 
-        // Trường hợp bị killed
-        if metadata.contains_key("killed") {
-            let max_rss = get_u64(metadata, "max-rss");
+    // Trường hợp chết bởi SIGSEGV (exitsig == "11"), hoặc SIGABRT (exitsig == "6")
+    // khi cấp phát thất bại (std::bad_alloc, Rust, Node)
+    if metadata
+        .get("exitsig")
+        .map(|v| v == "11" || v == "6")
+        .unwrap_or(false)
+    {
+        let max_rss = get_u64(metadata, "max-rss");
 
-            if max_rss as usize > (memory_limit_kb as f64 * 0.9) as usize {
-                return true;
-            }
+        if max_rss as usize > (memory_limit_kb as f64 * 0.95) as usize {
+            return true;
         }
+    }
 
-        // Trường hợp runtime error với status == "RE"
-        if metadata.get("status").map(|v| v == "RE").unwrap_or(false) {
-            let max_rss = get_u64(metadata, "max-rss");
+    // Trường hợp bị killed
+    if metadata.contains_key("killed") {
+        let max_rss = get_u64(metadata, "max-rss");
 
-            if max_rss as usize > (memory_limit_kb as f64 * 0.95) as usize {
-                return true;
-            }
+        if max_rss as usize > (memory_limit_kb as f64 * 0.9) as usize {
+            return true;
         }
+    }
+
+    // Trường hợp runtime error với status == "RE"
+    if metadata.get("status").map(|v| v == "RE").unwrap_or(false) {
+        let max_rss = get_u64(metadata, "max-rss");
 
-        false
+        if max_rss as usize > (memory_limit_kb as f64 * 0.95) as usize {
+            return true;
+        }
     }
 
+    false
+}
+
+fn check_result(
+    problem: &Problem,
+    test_case_slug: &str,
+    position: usize,
+) -> Result<TestResult, Box<dyn Error + Send + Sync>> {
+    info!("Checking result for test case: {}", test_case_slug);
+    
     let meta_data = metadata_file_to_hashmap(format!(
         "/var/local/lib/isolate/{}/box/meta.txt",
         env_tool::var("JUDGER_ID").unwrap()
@@ -300,6 +525,7 @@ fn check_result(problem: &Problem, test_case_slug: &str) -> Result<TestResult, B
         info!("Test case {} resulted in MLE", test_case_slug);
         return Ok(TestResult {
             slug: test_case_slug.to_string(),
+            position,
             status: Status::MLE,
             time: (get_f64(&meta_data, "time") * 1000.0) as u64,
             memory: 0,
@@ -310,12 +536,13 @@ fn check_result(problem: &Problem, test_case_slug: &str) -> Result<TestResult, B
         Some(status) => {
             if status == "TO" {
                 info!("Test case {} resulted in TLE", test_case_slug);
-                return Ok(TestResult {
+                Ok(TestResult {
                     slug: test_case_slug.to_string(),
+                    position,
                     status: Status::TLE,
                     time: 0,
                     memory: get_u64(&meta_data, "max-rss"),
-                });
+                })
             } else {
                 // Log runtime error details including stderr when status is not "TO"
                 let stderr_content = std::fs::read_to_string(format!(
@@ -326,12 +553,13 @@ fn check_result(problem: &Problem, test_case_slug: &str) -> Result<TestResult, B
                 info!("Test case {} resulted in RTE. Status: {}, Stderr: {}", 
                       test_case_slug, status, stderr_content);
                 
-                return Ok(TestResult {
+                Ok(TestResult {
                     slug: test_case_slug.to_string(),
+                    position,
                     status: Status::RTE,
                     time: (get_f64(&meta_data, "time") * 1000.0) as u64,
                     memory: get_u64(&meta_data, "max-rss"),
-                });
+                })
             }
         }
         _ => {
@@ -358,12 +586,13 @@ fn check_result(problem: &Problem, test_case_slug: &str) -> Result<TestResult, B
             match check_files(&actual_output_path, &expected_output_path) {
                 Ok(true) => {
                     info!("Test case {} resulted in AC", test_case_slug);
-                    return Ok(TestResult {
+                    Ok(TestResult {
                         slug: test_case_slug.to_string(),
+                        position,
                         status: Status::AC,
                         time: (get_f64(&meta_data, "time") * 1000.0) as u64,
                         memory: get_u64(&meta_data, "max-rss"),
-                    });
+                    })
                 }
                 Ok(false) => {
                     // Read the actual and expected outputs for logging
@@ -390,59 +619,189 @@ fn check_result(problem: &Problem, test_case_slug: &str) -> Result<TestResult, B
                     info!("Actual output bytes: {:?}", actual_bytes);
                     info!("Expected output bytes: {:?}", expected_bytes);
                     
-                    return Ok(TestResult {
+                    Ok(TestResult {
                         slug: test_case_slug.to_string(),
+                        position,
                         status: Status::WA,
                         time: (get_f64(&meta_data, "time") * 1000.0) as u64,
                         memory: get_u64(&meta_data, "max-rss"),
-                    });
+                    })
                 }
                 Err(e) => {
                     error!("Error reading output files for test case {}: {}", test_case_slug, e);
-                    return Ok(TestResult {
+                    Ok(TestResult {
                         slug: test_case_slug.to_string(),
+                        position,
                         status: Status::RTE,
                         time: (get_f64(&meta_data, "time") * 1000.0) as u64,
                         memory: get_u64(&meta_data, "max-rss"),
-                    });
+                    })
                 }
             }
         }
     }
 }
 
-pub async fn judge(job: &JudgerJob) -> Result<JudgerResult, Box<dyn Error>> {
+pub async fn judge(
+    ctx: &AppContext,
+    job: &JudgerJob,
+    cancel: &CancelToken<'_>,
+) -> Result<JudgerResult, Box<dyn Error + Send + Sync>> {
+    judge_selected(ctx, job, cancel, |_| true, false).await
+}
+
+/// Judge `job` on the test cases for which `is_selected` returns true, given
+/// the full `subtask/test` slug. Positions in the result still refer to the
+/// full ordered list of test cases. A rejudge does not reuse cached
+/// compilation errors.
+async fn judge_selected(
+    ctx: &AppContext,
+    job: &JudgerJob,
+    cancel: &CancelToken<'_>,
+    is_selected: impl Fn(&str) -> bool,
+    is_rejudge: bool,
+) -> Result<JudgerResult, Box<dyn Error + Send + Sync>> {
+    let result = judge_test_cases(ctx, job, cancel, is_selected, is_rejudge).await;
+    // Judging that failed or was cancelled may have downloaded test data too.
+    ctx.cache.evict(&job.problem_id);
+    result
+}
+
+async fn judge_test_cases(
+    ctx: &AppContext,
+    job: &JudgerJob,
+    cancel: &CancelToken<'_>,
+    is_selected: impl Fn(&str) -> bool,
+    is_rejudge: bool,
+) -> Result<JudgerResult, Box<dyn Error + Send + Sync>> {
     info!("Starting judge process for job: {}, problem: {}, language: {}", 
           job.id, job.problem_id, job.language);
+
+    if cancel.is_cancelled() {
+        info!("Job {} was cancelled before judging started", job.id);
+        return Ok(JudgerResult::cancelled(job.id));
+    }
           
-    let test_cases = get_test_cases(&job.problem_id).await?;
+    let test_cases: Vec<(usize, String)> = get_test_cases(&ctx.db, &job.problem_id)
+        .await?
+        .into_iter()
+        .enumerate()
+        .filter(|(_, test_case)| is_selected(test_case))
+        .collect();
 
-    let problem = get_problem(&job.problem_id).await?;
+    let language_config = ctx.languages.get(&job.language)?;
+    let toolchain = ctx.languages.version(&job.language);
 
-    let language_config = get_language_config(&job.language)?;
+    let problem = language_config.scale_limits(get_problem(&ctx.db, &job.problem_id).await?);
 
     create_isolate_box()?;
 
-    write_source_code(&job.source_code, &language_config.ext)?;
+    let mut commands = match language_config.commands_for(&job.source_code, &problem, &box_dir()) {
+        Ok(commands) => commands,
+        Err(log) => {
+            return Ok(JudgerResult {
+                id: job.id,
+                log,
+                status: ResultStatus::CE,
+                test_results: vec![],
+                toolchain: toolchain.clone(),
+            });
+        }
+    };
+
+    if let Some(dir) = ctx.languages.precompiled_header_dir(&job.language) {
+        commands.include_precompiled_headers(&dir);
+    }
+
+    write_source_code(&job.source_code, &commands.source_file)?;
+
+    ctx.status.set_compiling(&job.id);
 
     //compile source code
-    if !language_config.compile_command.is_empty() {
-        if let Err(res) = compile_source_code(&job.id, &language_config.compile_command) {
-            return Ok(res);
+    let compiled = if commands.compile_command.is_empty() {
+        None
+    } else {
+        let compiling = compile_with_cache(
+            ctx,
+            &commands,
+            &language_config,
+            toolchain.as_deref(),
+            &job.source_code,
+            !is_rejudge,
+        );
+        match unless_cancelled(cancel, &job.id, compiling).await {
+            Some(compiled) => compiled?,
+            None => {
+                cleanup_isolate_box()?;
+                return Ok(JudgerResult::cancelled(job.id));
+            }
+        }
+    };
+    if let Some(log) = compiled {
+        return Ok(JudgerResult {
+            id: job.id,
+            log,
+            status: ResultStatus::CE,
+            test_results: vec![],
+            toolchain: toolchain.clone(),
+        });
+    }
+
+    let checked = if commands.check_command.is_empty() {
+        None
+    } else {
+        let checking = check_source_code(&commands.check_command, &language_config);
+        match unless_cancelled(cancel, &job.id, checking).await {
+            Some(checked) => checked?,
+            None => {
+                cleanup_isolate_box()?;
+                return Ok(JudgerResult::cancelled(job.id));
+            }
         }
+    };
+    if let Some(log) = checked {
+        return Ok(JudgerResult {
+            id: job.id,
+            log,
+            status: ResultStatus::CE,
+            test_results: vec![],
+            toolchain: toolchain.clone(),
+        });
     }
 
+    let test_data = Arc::new(ctx.cache.open_problem(&ctx.s3, &job.problem_id).await?);
+
+    // Start downloading test data now that the submission compiled, so it
+    // overlaps with the execution of earlier tests.
+    let mut prefetcher = Prefetcher::start(
+        test_data.clone(),
+        ctx.s3.clone(),
+        test_cases.iter().map(|(_, test_case)| test_case.clone()).collect(),
+        ctx.config.prefetch_concurrency,
+    );
+
     let mut test_results: Vec<TestResult> = vec![];
     
     info!("Running {} test cases", test_cases.len());
 
     //run source code
-    for (index, test_case) in test_cases.iter().enumerate() {
+    for (index, (position, test_case)) in test_cases.iter().enumerate() {
         info!("Running test case {}/{}: {}", index + 1, test_cases.len(), test_case);
-        write_test_case_input(test_case, &problem).await?;
-        run_testcase(&language_config, &problem)?;
-        write_test_case_answer(test_case, &problem).await?;
-        let result = check_result(&problem, test_case)?;
+        if cancel.is_cancelled() {
+            info!("Job {} was cancelled, stopping before test case {}", job.id, test_case);
+            cleanup_isolate_box()?;
+            return Ok(JudgerResult::cancelled(job.id));
+        }
+        prefetcher.next().await?;
+        ctx.status.set_running(&job.id, *position);
+        write_test_case_input(test_case, &problem, &test_data, &ctx.s3).await?;
+        if let RunOutcome::Cancelled =
+            run_testcase(&language_config, &commands.run_command, &problem, cancel, &job.id).await?
+        {
+            return Ok(JudgerResult::cancelled(job.id));
+        }
+        write_test_case_answer(test_case, &problem, &test_data, &ctx.s3).await?;
+        let result = check_result(&problem, test_case, *position)?;
         test_results.push(result);
         info!("Completed test case {}/{}: {} with status {:?}", 
               index + 1, test_cases.len(), test_case, test_results.last().unwrap().status);
@@ -450,9 +809,354 @@ pub async fn judge(job: &JudgerJob) -> Result<JudgerResult, Box<dyn Error>> {
 
     info!("Judge process completed successfully for job: {}", job.id);
     Ok(JudgerResult {
-        id: job.id.clone(),
+        id: job.id,
         log: "".to_string(),
         status: ResultStatus::OK,
         test_results,
+        toolchain,
     })
-}
\ No newline at end of file
+}
+
+fn read_box_file_truncated(file_name: &str) -> String {
+    let content = fs::read(format!(
+        "/var/local/lib/isolate/{}/box/{}",
+        env_tool::var("JUDGER_ID").unwrap(),
+        file_name
+    ))
+    .unwrap_or_default();
+
+    let truncated = &content[..content.len().min(RUN_OUTPUT_LIMIT)];
+    String::from_utf8_lossy(truncated).to_string()
+}
+
+/// Compile and run the source code once against user-provided stdin, with the
+/// problem's limits. No output checking is done.
+pub async fn run(
+    ctx: &AppContext,
+    job: &RunJob,
+    cancel: &CancelToken<'_>,
+) -> Result<RunResult, Box<dyn Error + Send + Sync>> {
+    info!("Starting run process for job: {}, problem: {}, language: {}",
+          job.id, job.problem_id, job.language);
+
+    if cancel.is_cancelled() {
+        info!("Job {} was cancelled before running started", job.id);
+        return Ok(RunResult::cancelled(job.id));
+    }
+
+    let language_config = ctx.languages.get(&job.language)?;
+    let toolchain = ctx.languages.version(&job.language);
+
+    let problem = language_config.scale_limits(get_problem(&ctx.db, &job.problem_id).await?);
+
+    create_isolate_box()?;
+
+    let mut commands = match language_config.commands_for(&job.source_code, &problem, &box_dir()) {
+        Ok(commands) => commands,
+        Err(log) => {
+            return Ok(RunResult {
+                id: job.id,
+                log,
+                status: ResultStatus::CE,
+                toolchain: toolchain.clone(),
+                ..RunResult::default()
+            });
+        }
+    };
+
+    if let Some(dir) = ctx.languages.precompiled_header_dir(&job.language) {
+        commands.include_precompiled_headers(&dir);
+    }
+
+    write_source_code(&job.source_code, &commands.source_file)?;
+
+    ctx.status.set_compiling(&job.id);
+
+    let compiled = if commands.compile_command.is_empty() {
+        None
+    } else {
+        let compiling = compile_with_cache(
+            ctx,
+            &commands,
+            &language_config,
+            toolchain.as_deref(),
+            &job.source_code,
+            true,
+        );
+        match unless_cancelled(cancel, &job.id, compiling).await {
+            Some(compiled) => compiled?,
+            None => {
+                cleanup_isolate_box()?;
+                return Ok(RunResult::cancelled(job.id));
+            }
+        }
+    };
+    if let Some(log) = compiled {
+        return Ok(RunResult {
+            id: job.id,
+            log,
+            status: ResultStatus::CE,
+            toolchain: toolchain.clone(),
+            ..RunResult::default()
+        });
+    }
+
+    let checked = if commands.check_command.is_empty() {
+        None
+    } else {
+        let checking = check_source_code(&commands.check_command, &language_config);
+        match unless_cancelled(cancel, &job.id, checking).await {
+            Some(checked) => checked?,
+            None => {
+                cleanup_isolate_box()?;
+                return Ok(RunResult::cancelled(job.id));
+            }
+        }
+    };
+    if let Some(log) = checked {
+        return Ok(RunResult {
+            id: job.id,
+            log,
+            status: ResultStatus::CE,
+            toolchain: toolchain.clone(),
+            ..RunResult::default()
+        });
+    }
+
+    let (input_file_name, output_file_name) = if problem.io_mode == IoMode::Standard {
+        ("input.txt", "output.txt")
+    } else {
+        (
+            problem.input_file.as_deref().unwrap(),
+            problem.output_file.as_deref().unwrap(),
+        )
+    };
+
+    fs::write(
+        format!(
+            "/var/local/lib/isolate/{}/box/{}",
+            env_tool::var("JUDGER_ID").unwrap(),
+            input_file_name
+        ),
+        &job.stdin,
+    )?;
+
+    ctx.status.set_running(&job.id, 0);
+
+    if let RunOutcome::Cancelled =
+        run_testcase(&language_config, &commands.run_command, &problem, cancel, &job.id).await?
+    {
+        return Ok(RunResult::cancelled(job.id));
+    }
+
+    let meta_data = metadata_file_to_hashmap(format!(
+        "/var/local/lib/isolate/{}/box/meta.txt",
+        env_tool::var("JUDGER_ID").unwrap()
+    ))?;
+
+    let verdict = if is_memory_limit_exceeded(&meta_data, problem.memory_limit as usize) {
+        Some(Status::MLE)
+    } else {
+        match meta_data.get("status").map(String::as_str) {
+            Some("TO") => Some(Status::TLE),
+            Some(_) => Some(Status::RTE),
+            None => None,
+        }
+    };
+
+    info!("Run process completed successfully for job: {}", job.id);
+    Ok(RunResult {
+        id: job.id,
+        log: "".to_string(),
+        status: ResultStatus::OK,
+        verdict,
+        stdout: read_box_file_truncated(output_file_name),
+        stderr: read_box_file_truncated("stderr.txt"),
+        exit_code: meta_data.get("exitcode").and_then(|v| v.parse().ok()),
+        exit_signal: meta_data.get("exitsig").and_then(|v| v.parse().ok()),
+        time: (get_f64(&meta_data, "time") * 1000.0) as u64,
+        memory: get_u64(&meta_data, "max-rss"),
+        toolchain,
+    })
+}
+
+/// Rejudge a submission, optionally only on some subtasks or tests, and report
+/// which test results differ from the verdicts it had before.
+pub async fn rejudge(
+    ctx: &AppContext,
+    job: &RejudgeJob,
+    cancel: &CancelToken<'_>,
+) -> Result<RejudgeResult, Box<dyn Error + Send + Sync>> {
+    info!("Starting rejudge for job: {}, subtasks: {:?}, tests: {:?}",
+          job.id, job.subtasks, job.tests);
+
+    let is_selected = |test_case: &str| {
+        let subtask = test_case.split('/').next().unwrap_or_default();
+        (job.subtasks.is_empty() && job.tests.is_empty())
+            || job.subtasks.iter().any(|s| s == subtask)
+            || job.tests.iter().any(|t| t == test_case)
+    };
+
+    let result = judge_selected(ctx, &job.to_judger_job(), cancel, is_selected, true).await?;
+    if let ResultStatus::Cancelled = result.status {
+        return Ok(RejudgeResult { result, changes: vec![] });
+    }
+
+    let changes = test_result_changes(&job.previous_results, &result.test_results, is_selected);
+
+    info!("Rejudge completed for job: {}, {} test results changed", job.id, changes.len());
+    Ok(RejudgeResult { result, changes })
+}
+
+/// Selected tests whose verdict differs from the one before the rejudge, in
+/// judging order, then those that had a verdict before but were not run now,
+/// e.g. because they were removed or the submission no longer compiles.
+fn test_result_changes(
+    previous_results: &[PreviousTestResult],
+    test_results: &[TestResult],
+    is_selected: impl Fn(&str) -> bool,
+) -> Vec<TestResultChange> {
+    let previous: HashMap<&str, &Status> = previous_results
+        .iter()
+        .filter(|previous| is_selected(&previous.slug))
+        .map(|previous| (previous.slug.as_str(), &previous.status))
+        .collect();
+
+    let mut changes: Vec<TestResultChange> = test_results
+        .iter()
+        .filter(|current| previous.get(current.slug.as_str()) != Some(&&current.status))
+        .map(|current| TestResultChange {
+            slug: current.slug.clone(),
+            previous: previous.get(current.slug.as_str()).map(|status| (*status).clone()),
+            current: Some(current.status.clone()),
+        })
+        .collect();
+
+    for previous in previous_results {
+        if is_selected(&previous.slug)
+            && !test_results.iter().any(|current| current.slug == previous.slug)
+            && !changes.iter().any(|change| change.slug == previous.slug)
+        {
+            changes.push(TestResultChange {
+                slug: previous.slug.clone(),
+                previous: Some(previous.status.clone()),
+                current: None,
+            });
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn previous(results: &[(&str, Status)]) -> Vec<PreviousTestResult> {
+        results
+            .iter()
+            .map(|(slug, status)| PreviousTestResult {
+                slug: slug.to_string(),
+                status: status.clone(),
+            })
+            .collect()
+    }
+
+    fn current(results: &[(&str, Status)]) -> Vec<TestResult> {
+        results
+            .iter()
+            .enumerate()
+            .map(|(position, (slug, status))| TestResult {
+                slug: slug.to_string(),
+                position,
+                status: status.clone(),
+                time: 0,
+                memory: 0,
+            })
+            .collect()
+    }
+
+    fn changes(
+        previous_results: &[(&str, Status)],
+        test_results: &[(&str, Status)],
+        is_selected: impl Fn(&str) -> bool,
+    ) -> Vec<(String, Option<Status>, Option<Status>)> {
+        test_result_changes(&previous(previous_results), &current(test_results), is_selected)
+            .into_iter()
+            .map(|change| (change.slug, change.previous, change.current))
+            .collect()
+    }
+
+    fn all(_: &str) -> bool {
+        true
+    }
+
+    #[test]
+    fn unchanged_verdicts_are_not_reported() {
+        let results = [("1/a", Status::AC), ("1/b", Status::WA)];
+        assert!(changes(&results, &results, all).is_empty());
+    }
+
+    #[test]
+    fn changed_verdicts_are_reported_in_judging_order() {
+        assert_eq!(
+            changes(
+                &[("1/b", Status::TLE), ("1/a", Status::AC), ("1/c", Status::AC)],
+                &[("1/a", Status::WA), ("1/b", Status::AC), ("1/c", Status::AC)],
+                all
+            ),
+            [
+                ("1/a".to_string(), Some(Status::AC), Some(Status::WA)),
+                ("1/b".to_string(), Some(Status::TLE), Some(Status::AC)),
+            ]
+        );
+    }
+
+    #[test]
+    fn added_and_removed_tests_have_no_verdict_on_one_side() {
+        assert_eq!(
+            changes(
+                &[("1/a", Status::AC), ("1/old", Status::WA), ("2/gone", Status::AC)],
+                &[("1/a", Status::AC), ("1/new", Status::RTE)],
+                all
+            ),
+            [
+                ("1/new".to_string(), None, Some(Status::RTE)),
+                ("1/old".to_string(), Some(Status::WA), None),
+                ("2/gone".to_string(), Some(Status::AC), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn compilation_error_removes_every_verdict() {
+        assert_eq!(
+            changes(&[("1/a", Status::AC), ("1/b", Status::WA)], &[], all),
+            [
+                ("1/a".to_string(), Some(Status::AC), None),
+                ("1/b".to_string(), Some(Status::WA), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_selected_tests_are_compared() {
+        let is_selected = |slug: &str| slug.starts_with("2/");
+        assert_eq!(
+            changes(
+                &[("1/a", Status::AC), ("1/b", Status::AC), ("2/a", Status::AC)],
+                &[("2/a", Status::MLE)],
+                is_selected
+            ),
+            [("2/a".to_string(), Some(Status::AC), Some(Status::MLE))]
+        );
+    }
+
+    #[test]
+    fn duplicate_previous_results_are_reported_once() {
+        assert_eq!(
+            changes(&[("1/a", Status::AC), ("1/a", Status::AC)], &[], all),
+            [("1/a".to_string(), Some(Status::AC), None)]
+        );
+    }
+}
@@ -1,106 +1,508 @@
-use crate::models::LanguageConfig;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
 use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{SignalKind, signal};
+use tracing::{error, info, warn};
+
+use crate::env_tool;
+use crate::models::LanguageConfig;
+use crate::pch;
+
+/// Built-in language table, used when no languages file is configured.
+pub const BUILTIN_LANGUAGES: &str = include_str!("../languages.toml");
+
+/// How often the languages file is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LanguagesFile {
+    language: Vec<LanguageConfig>,
+}
+
+pub fn parse_languages(content: &str) -> Result<Vec<LanguageConfig>, Box<dyn Error + Send + Sync>> {
+    let languages = toml::from_str::<LanguagesFile>(content)?.language;
+
+    let mut ids = HashSet::new();
+    for language in &languages {
+        language
+            .validate()
+            .map_err(|e| format!("language {:?}: {}", language.id, e))?;
+        if !ids.insert(language.id.as_str()) {
+            return Err(format!("language {:?} is defined twice", language.id).into());
+        }
+    }
+
+    if languages.is_empty() {
+        return Err("no languages are defined".into());
+    }
+
+    Ok(languages)
+}
+
+/// Output of a toolchain command, stdout followed by stderr, as some tools
+/// print their version on stderr.
+fn command_output(command: &[String], env: &BTreeMap<String, String>) -> Result<String, String> {
+    let output = Command::new(&command[0])
+        .args(&command[1..])
+        .envs(env)
+        .output()
+        .map_err(|e| format!("`{}` failed: {}", command.join(" "), e))?;
+
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    if !output.status.success() {
+        return Err(format!(
+            "`{}` exited with {}: {}",
+            command.join(" "),
+            output.status,
+            text.trim()
+        ));
+    }
+    Ok(text)
+}
+
+/// Whether `program` can be started, either as a path or found in `PATH`.
+fn find_program(program: &str) -> bool {
+    if program.contains('/') {
+        return Path::new(program).is_file();
+    }
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+/// Check that the programs of `language` exist and are the configured
+/// version, returning the version string of its toolchain.
+fn probe_toolchain(language: &LanguageConfig) -> Result<String, String> {
+    for command in [
+        &language.compile_command,
+        &language.check_command,
+        &language.run_command,
+    ] {
+        // Programs built in the box only exist once a submission is compiled.
+        if let Some(program) = command.program()
+            && !find_program(program)
+        {
+            return Err(format!("{} is not installed", program));
+        }
+    }
+
+    let version = command_output(&language.version_command, &language.env)?
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| format!("`{}` printed nothing", language.version_command.join(" ")))?;
+
+    for check in &language.version_checks {
+        let text = command_output(&check.command, &language.env)?;
+        if !text.contains(&check.expect) {
+            return Err(format!(
+                "`{}` does not report {:?}: {}",
+                check.command.join(" "),
+                check.expect,
+                text.trim()
+            ));
+        }
+    }
+
+    Ok(version)
+}
+
+/// Drop languages whose toolchain is missing or not the configured version,
+/// so a submission is never judged with a different compiler than it asked
+/// for, nor blamed for one that is not installed. Returns the remaining
+/// languages with their toolchain versions.
+fn probe_toolchains(languages: Vec<LanguageConfig>) -> Vec<(LanguageConfig, String)> {
+    languages
+        .into_iter()
+        .filter_map(|language| match probe_toolchain(&language) {
+            Ok(version) => {
+                info!("Language {}: {}", language.id, version);
+                Some((language, version))
+            }
+            Err(e) => {
+                error!("Disabling language {}: {}", language.id, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Language definitions, loaded from `JUDGER_LANGUAGES_FILE` when that file
+/// exists and from the built-in table otherwise.
+///
+/// Changes to the file apply to the next job. Languages added or removed by a
+/// reload only change the queues this judger pulls from after a restart.
+pub struct LanguageTable {
+    path: PathBuf,
+    languages: RwLock<Vec<Arc<LanguageConfig>>>,
+    /// Toolchain version of each enabled language.
+    versions: RwLock<BTreeMap<String, String>>,
+    pch_root: PathBuf,
+    /// Include directory with the precompiled header of each language that
+    /// has one.
+    precompiled: RwLock<BTreeMap<String, PathBuf>>,
+    /// Every defined language, including those disabled on this judger.
+    known: RwLock<Vec<String>>,
+    modified: RwLock<Option<SystemTime>>,
+}
+
+impl LanguageTable {
+    pub fn from_env() -> Result<LanguageTable, Box<dyn Error + Send + Sync>> {
+        let path = PathBuf::from(env_tool::env_or_default(
+            "JUDGER_LANGUAGES_FILE",
+            "/etc/judger/languages.toml",
+        ));
+
+        let table = LanguageTable {
+            path,
+            languages: RwLock::new(vec![]),
+            versions: RwLock::new(BTreeMap::new()),
+            pch_root: pch::pch_dir_from_env(),
+            precompiled: RwLock::new(BTreeMap::new()),
+            known: RwLock::new(vec![]),
+            modified: RwLock::new(None),
+        };
+        table.load()?;
+        Ok(table)
+    }
+
+    fn modified_time(&self) -> Option<SystemTime> {
+        fs::metadata(&self.path).and_then(|meta| meta.modified()).ok()
+    }
+
+    fn load(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let modified = self.modified_time();
+        let languages = if modified.is_some() {
+            let content = fs::read_to_string(&self.path)?;
+            parse_languages(&content).map_err(|e| format!("{}: {}", self.path.display(), e))?
+        } else {
+            parse_languages(BUILTIN_LANGUAGES)?
+        };
+        let known = languages.iter().map(|language| language.id.clone()).collect();
+        let (languages, versions): (Vec<_>, BTreeMap<_, _>) = probe_toolchains(languages)
+            .into_iter()
+            .map(|(language, version)| {
+                let id = language.id.clone();
+                (Arc::new(language), (id, version))
+            })
+            .unzip();
+
+        // A language whose header fails to build still works, only slower.
+        let precompiled = languages
+            .iter()
+            .filter_map(|language| {
+                let header = language.precompiled_header.as_ref()?;
+                match pch::prepare(&self.pch_root, language, header, &versions[&language.id]) {
+                    Ok(dir) => Some((language.id.clone(), dir)),
+                    Err(e) => {
+                        warn!("Not precompiling {} for {}: {}", header, language.id, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        info!(
+            "Loaded {} languages from {}",
+            languages.len(),
+            if modified.is_some() {
+                self.path.display().to_string()
+            } else {
+                "the built-in table".to_string()
+            }
+        );
+
+        *self.languages.write().unwrap() = languages;
+        *self.versions.write().unwrap() = versions;
+        *self.precompiled.write().unwrap() = precompiled;
+        *self.known.write().unwrap() = known;
+        *self.modified.write().unwrap() = modified;
+        Ok(())
+    }
+
+    /// Reload the definitions, keeping the current ones if the file is invalid.
+    pub fn reload(&self) {
+        if let Err(e) = self.load() {
+            error!("Keeping the current languages, failed to reload: {}", e);
+        }
+    }
+
+    /// Reload on SIGHUP and whenever the languages file changes. Reloading
+    /// probes toolchains and builds precompiled headers, so it runs on the
+    /// blocking thread pool.
+    pub async fn watch(self: Arc<Self>) {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => Some(hangup),
+            Err(e) => {
+                warn!("Failed to listen for SIGHUP, only watching the file: {}", e);
+                None
+            }
+        };
+
+        loop {
+            tokio::select! {
+                Some(_) = async { hangup.as_mut()?.recv().await } => {
+                    info!("Received SIGHUP, reloading languages");
+                    self.reload_in_background().await;
+                }
+                _ = tokio::time::sleep(WATCH_INTERVAL) => {
+                    if self.modified_time() != *self.modified.read().unwrap() {
+                        info!("Languages file changed, reloading");
+                        self.reload_in_background().await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn reload_in_background(self: &Arc<Self>) {
+        let table = self.clone();
+        if let Err(e) = tokio::task::spawn_blocking(move || table.reload()).await {
+            error!("Failed to reload languages: {}", e);
+        }
+    }
+
+    /// Every defined language, whether or not this judger can judge it.
+    pub fn known_names(&self) -> Vec<String> {
+        self.known.read().unwrap().clone()
+    }
+
+    pub fn is_known(&self, language: &str) -> bool {
+        self.known.read().unwrap().iter().any(|name| name == language)
+    }
+
+    /// Languages this judger can judge.
+    pub fn names(&self) -> Vec<String> {
+        self.languages
+            .read()
+            .unwrap()
+            .iter()
+            .map(|language| language.id.clone())
+            .collect()
+    }
+
+    /// Toolchain version of each language this judger can judge.
+    pub fn versions(&self) -> BTreeMap<String, String> {
+        self.versions.read().unwrap().clone()
+    }
+
+    pub fn version(&self, language: &str) -> Option<String> {
+        self.versions.read().unwrap().get(language).cloned()
+    }
+
+    /// Include directory with the precompiled header of a language, if any.
+    pub fn precompiled_header_dir(&self, language: &str) -> Option<PathBuf> {
+        self.precompiled.read().unwrap().get(language).cloned()
+    }
+
+    pub fn get(&self, language: &str) -> Result<Arc<LanguageConfig>, Box<dyn Error + Send + Sync>> {
+        self.languages
+            .read()
+            .unwrap()
+            .iter()
+            .find(|config| config.id == language)
+            .cloned()
+            .ok_or_else(|| "Language not found".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judger::is_memory_limit_exceeded;
+    use crate::models::{IoMode, Problem};
+    use std::collections::HashMap;
+    use std::io::Write;
+    use std::process::Stdio;
+    use std::time::Instant;
+    use uuid::Uuid;
+
+    const SAMPLES: [&str; 3] = ["ac", "tle", "mle"];
+
+    /// Sample solution of `kind` to the a + b problem, shared by the languages
+    /// with the same source file extension.
+    fn sample_path(language: &LanguageConfig, kind: &str) -> PathBuf {
+        let extension = Path::new(&language.source_file)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap();
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/samples")
+            .join(format!("{}.{}", kind, extension))
+    }
+
+    #[test]
+    fn builtin_languages_have_samples() {
+        for language in parse_languages(BUILTIN_LANGUAGES).unwrap() {
+            for kind in SAMPLES {
+                let path = sample_path(&language, kind);
+                assert!(path.is_file(), "{}: missing {}", language.id, path.display());
+            }
+        }
+    }
+
+    /// How a sample run ended, in the order the judger checks for them.
+    enum Outcome {
+        MemoryLimitExceeded,
+        TimedOut,
+        Failed,
+        Output(String),
+    }
+
+    /// Wait for `pid` without blocking, returning its wait status and peak
+    /// resident memory in KB once it has exited.
+    fn try_wait4(pid: u32, options: i32) -> Option<(i32, u64)> {
+        let mut status = 0;
+        // SAFETY: rusage is plain data, filled in by wait4.
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        let waited = unsafe { libc::wait4(pid as i32, &mut status, options, &mut usage) };
+        assert!(waited >= 0, "wait4 failed");
+        (waited != 0).then_some((status, usage.ru_maxrss as u64))
+    }
+
+    /// The part of the sandbox metadata the judger's verdict depends on.
+    fn metadata(status: i32, max_rss_kb: u64, timed_out: bool) -> HashMap<String, String> {
+        let mut metadata = HashMap::from([("max-rss".to_string(), max_rss_kb.to_string())]);
+        if timed_out {
+            metadata.insert("status".to_string(), "TO".to_string());
+            metadata.insert("killed".to_string(), "1".to_string());
+        } else if libc::WIFSIGNALED(status) {
+            metadata.insert("status".to_string(), "SG".to_string());
+            metadata.insert("exitsig".to_string(), libc::WTERMSIG(status).to_string());
+        } else if libc::WEXITSTATUS(status) != 0 {
+            metadata.insert("status".to_string(), "RE".to_string());
+            metadata.insert("exitcode".to_string(), libc::WEXITSTATUS(status).to_string());
+        }
+        metadata
+    }
+
+    fn run_command(command: &[String], dir: &Path, env: &BTreeMap<String, String>) {
+        let status = Command::new(&command[0])
+            .args(&command[1..])
+            .current_dir(dir)
+            .envs(env)
+            .status()
+            .unwrap();
+        assert!(status.success(), "`{}` failed", command.join(" "));
+    }
+
+    /// Compile and run a sample on `1 2` outside the sandbox, with the address
+    /// space limit the sandbox would set.
+    #[allow(clippy::zombie_processes)] // reaped with wait4
+    fn run_sample(language: &LanguageConfig, kind: &str) -> Outcome {
+        let source = fs::read_to_string(sample_path(language, kind)).unwrap();
+        let dir = env::temp_dir().join(format!(
+            "judger-sample-{}-{}-{}",
+            language.id,
+            kind,
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+
+        let problem = language.scale_limits(Problem {
+            io_mode: IoMode::Standard,
+            input_file: None,
+            output_file: None,
+            time_limit: 1000,
+            memory_limit: 256 * 1024,
+            id: Uuid::nil(),
+            compile_flags: vec![],
+        });
+        let commands = language
+            .commands_for(&source, &problem, dir.to_str().unwrap())
+            .unwrap();
+        fs::write(dir.join(&commands.source_file), &source).unwrap();
+
+        for command in [&commands.compile_command, &commands.check_command] {
+            if !command.is_empty() {
+                run_command(command, &dir, &language.env);
+            }
+        }
+
+        let address_space_kb = problem.memory_limit + language.runtime_overhead_mb * 1024;
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(r#"ulimit -v "$0" && exec "$@""#)
+            .arg(address_space_kb.to_string())
+            .args(&commands.run_command)
+            .current_dir(&dir)
+            .envs(&language.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"1 2\n").unwrap();
+
+        // Reaped with wait4 rather than through `child`, for the peak memory.
+        let deadline = Instant::now() + Duration::from_millis(problem.time_limit * 3);
+        let (status, max_rss_kb, timed_out) = loop {
+            if let Some((status, max_rss_kb)) = try_wait4(child.id(), libc::WNOHANG) {
+                break (status, max_rss_kb, false);
+            }
+            if Instant::now() > deadline {
+                child.kill().unwrap();
+                let (status, max_rss_kb) = try_wait4(child.id(), 0).unwrap();
+                break (status, max_rss_kb, true);
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        };
+        let mut stdout = String::new();
+        std::io::Read::read_to_string(&mut child.stdout.take().unwrap(), &mut stdout).unwrap();
+
+        let metadata = metadata(status, max_rss_kb, timed_out);
+        let outcome = if is_memory_limit_exceeded(&metadata, problem.memory_limit as usize) {
+            Outcome::MemoryLimitExceeded
+        } else if timed_out {
+            Outcome::TimedOut
+        } else if metadata.contains_key("status") {
+            Outcome::Failed
+        } else {
+            Outcome::Output(stdout.trim().to_string())
+        };
+
+        fs::remove_dir_all(&dir).unwrap();
+        outcome
+    }
+
+    /// Needs the toolchains of the judger image, run with
+    /// `cargo test -- --ignored`. Languages whose toolchain is missing are
+    /// skipped. Verdicts are given by the judger's own memory check, from the
+    /// peak memory of the run.
+    #[test]
+    #[ignore]
+    fn samples_get_their_verdicts() {
+        for language in parse_languages(BUILTIN_LANGUAGES).unwrap() {
+            if let Err(e) = probe_toolchain(&language) {
+                eprintln!("Skipping {}: {}", language.id, e);
+                continue;
+            }
 
-const LANGUAGES: &[LanguageConfig] = &[
-    // C
-    LanguageConfig {
-        language: "C99",
-        ext: "c",
-        compile_command: "gcc -std=c99 main.c -o main",
-        run_command: "./main",
-    },
-    LanguageConfig {
-        language: "C11",
-        ext: "c",
-        compile_command: "gcc -std=c11 main.c -o main",
-        run_command: "./main",
-    },
-    LanguageConfig {
-        language: "C17",
-        ext: "c",
-        compile_command: "gcc -std=c17 main.c -o main",
-        run_command: "./main",
-    },
-    LanguageConfig {
-        language: "C23",
-        ext: "c",
-        compile_command: "gcc -std=c2x main.c -o main",
-        run_command: "./main",
-    },
-    // C++
-    LanguageConfig {
-        language: "CPP03",
-        ext: "cpp",
-        compile_command: "g++ -std=c++03 main.cpp -o main",
-        run_command: "./main",
-    },
-    LanguageConfig {
-        language: "CPP11",
-        ext: "cpp",
-        compile_command: "g++ -std=c++11 main.cpp -o main",
-        run_command: "./main",
-    },
-    LanguageConfig {
-        language: "CPP14",
-        ext: "cpp",
-        compile_command: "g++ -std=c++14 main.cpp -o main",
-        run_command: "./main",
-    },
-    LanguageConfig {
-        language: "CPP17",
-        ext: "cpp",
-        compile_command: "g++ -std=c++17 main.cpp -o main",
-        run_command: "./main",
-    },
-    LanguageConfig {
-        language: "CPP20",
-        ext: "cpp",
-        compile_command: "g++ -std=c++20 main.cpp -o main",
-        run_command: "./main",
-    },
-    LanguageConfig {
-        language: "CPP23",
-        ext: "cpp",
-        compile_command: "g++ -std=c++23 main.cpp -o main",
-        run_command: "./main",
-    },
-    // Java
-    LanguageConfig {
-        language: "JAVA_8",
-        ext: "java",
-        compile_command: "javac main.java",
-        run_command: "java main",
-    },
-    LanguageConfig {
-        language: "JAVA_11",
-        ext: "java",
-        compile_command: "javac main.java",
-        run_command: "java main",
-    },
-    LanguageConfig {
-        language: "JAVA_17",
-        ext: "java",
-        compile_command: "javac main.java",
-        run_command: "java main",
-    },
-    // Python
-    LanguageConfig {
-        language: "PYTHON2",
-        ext: "py",
-        compile_command: "",
-        run_command: "python2 main.py",
-    },
-    LanguageConfig {
-        language: "PYTHON3",
-        ext: "py",
-        compile_command: "",
-        run_command: "python3 main.py",
-    },
-];
-
-pub fn get_language_config(language: &str) -> Result<&'static LanguageConfig, Box<dyn Error>> {
-    LANGUAGES
-        .iter()
-        .find(|&lang| lang.language == language)
-        .ok_or_else(|| "Language not found".into())
+            match run_sample(&language, "ac") {
+                Outcome::Output(output) => assert_eq!(output, "3", "{}: ac", language.id),
+                _ => panic!("{}: ac did not finish normally", language.id),
+            }
+            assert!(
+                matches!(run_sample(&language, "tle"), Outcome::TimedOut),
+                "{}: tle was not judged TLE",
+                language.id
+            );
+            assert!(
+                matches!(run_sample(&language, "mle"), Outcome::MemoryLimitExceeded),
+                "{}: mle was not judged MLE",
+                language.id
+            );
+        }
+    }
 }
@@ -1,273 +1,645 @@
 use chrono::Utc;
 use dotenvy::dotenv;
-use futures_lite::stream::StreamExt;
-use lapin::{
-    BasicProperties, Connection, ConnectionProperties, message::Delivery, options::*,
-    types::FieldTable,
-};
-use serde::{Deserialize, Serialize};
-use serde_json;
-use tokio;
+use lapin::{message::Delivery, options::*};
+use protocol::{JobMessage, JudgerMessage, Outgoing};
+use std::error::Error;
+use std::sync::Arc;
 use tracing::{debug, error, info};
-use tracing_subscriber;
+mod broker;
+mod cache;
+mod cancel;
+mod capabilities;
 mod checker;
+mod compile_cache;
+mod context;
+mod dead_letter;
 mod db;
+mod fingerprint;
+mod java;
 mod judger;
 mod languages;
 mod metadata;
 mod minio;
 mod models;
+mod natural_sort;
+mod outbox;
+mod pch;
+mod prefetch;
+mod protocol;
 mod rabbitmq;
+mod scheduler;
+mod shutdown;
+mod status;
+mod template;
 mod env_tool;
 
 #[tokio::main]
 async fn main() {
+    // `judger schema` prints the JSON Schema of the message protocol.
+    if std::env::args().nth(1).as_deref() == Some("schema") {
+        println!("{}", serde_json::to_string_pretty(&protocol::schema()).unwrap());
+        return;
+    }
+
     dotenv().ok();
     tracing_subscriber::fmt::init();
 
-    info!("Judger is starting...");
-
-    let judger_id = env_tool::var("JUDGER_ID").unwrap();
-    info!("Judger ID: {}", judger_id);
+    // `judger precompile` builds the precompiled headers of the languages,
+    // so an image can ship them instead of building them at startup.
+    if std::env::args().nth(1).as_deref() == Some("precompile") {
+        if let Err(e) = languages::LanguageTable::from_env() {
+            error!("Failed to load languages: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    let rabbitmq_url = rabbitmq::get_rabbitmq_url();
+    info!("Judger is starting...");
 
-    let conn = Connection::connect(&rabbitmq_url, ConnectionProperties::default())
-        .await
-        .expect("failed to connect");
+    let mut shutdown_signal = shutdown::spawn_signal_listener();
 
-    info!("Connected to RabbitMQ at: {}", rabbitmq_url);
+    let ctx = Arc::new(context::AppContext::new().expect("failed to create application context"));
+    let judger_id = ctx.config.judger_id.clone();
+    info!("Judger ID: {}", judger_id);
 
-    let channel = conn
-        .create_channel()
-        .await
-        .expect("failed to create channel");
+    let outbox =
+        outbox::Outbox::open(&outbox::outbox_dir_from_env()).expect("failed to open the outbox");
+    let broker = Arc::new(broker::Broker::new(
+        outbox,
+        ctx.config.max_buffered_messages,
+    ));
 
     // spawn heartbeat task
-    let heartbeat_channel = channel.clone();
-    tokio::spawn(async move {
+    let heartbeat_broker = broker.clone();
+    let heartbeat_ctx = ctx.clone();
+    let heartbeat_task = tokio::spawn(async move {
         let mut ticker = tokio::time::interval(tokio::time::Duration::from_secs(10)); // 10s một lần
         loop {
             ticker.tick().await;
-            send_heartbeat_message(&heartbeat_channel).await;
+            send_heartbeat_message(&heartbeat_ctx, &heartbeat_broker).await;
+            heartbeat_broker.retry().await;
         }
     });
 
-    //judger job queue
-    channel
-        .queue_declare(
-            "judger.job",
-            QueueDeclareOptions {
-                durable: true,
-                ..QueueDeclareOptions::default()
-            },
-            FieldTable::default(),
-        )
-        .await
-        .expect("failed to declare queue");
-
-    channel
-        .basic_qos(1, BasicQosOptions::default())
-        .await
-        .expect("failed to set QoS");
-
-    let tag = format!("judger_consumer_{}", judger_id);
-
-    let mut consumer = channel
-        .basic_consume(
-            "judger.job",
-            &tag,
-            BasicConsumeOptions::default(),
-            FieldTable::default(),
-        )
-        .await
-        .expect("failed to create consumer");
-
-    info!(
-        "Judger is ready to receive messages with consumer tag: {}",
-        tag
-    );
-
-    while let Some(delivery_result) = consumer.next().await {
-        match delivery_result {
-            Ok(delivery) => {
-                handle_message(&channel, delivery).await;
+    let languages_task = tokio::spawn(ctx.languages.clone().watch());
+
+    let mut backoff = rabbitmq::Backoff::new();
+
+    // Supervisor loop: (re)connect with backoff and consume until the
+    // connection is lost or a shutdown is requested.
+    loop {
+        let languages = ctx.languages.known_names();
+        let session = tokio::select! {
+            _ = shutdown::requested(&mut shutdown_signal) => break,
+            session = rabbitmq::open_session(&judger_id, &languages) => session,
+        };
+
+        let mut session = match session {
+            Ok(session) => session,
+            Err(e) => {
+                let delay = backoff.next_delay();
+                error!(
+                    "Failed to connect to RabbitMQ: {:?}, retrying in {}s",
+                    e,
+                    delay.as_secs()
+                );
+                tokio::select! {
+                    _ = shutdown::requested(&mut shutdown_signal) => break,
+                    _ = tokio::time::sleep(delay) => continue,
+                }
             }
+        };
+
+        backoff.reset();
+        broker.attach(session.channel.clone()).await;
+
+        let cancellations = ctx.cancellations.clone();
+        let cancel_consumer = session.cancel_consumer.clone();
+        let cancel_task = tokio::spawn(async move { cancellations.listen(cancel_consumer).await });
+
+        info!("Judger is ready to receive messages");
+
+        let stopped = consume_jobs(&ctx, &broker, &mut session, &mut shutdown_signal).await;
+
+        broker.detach().await;
+        cancel_task.abort();
+
+        if stopped {
+            if let Err(e) = session.channel.close(200, "judger shutting down").await {
+                error!("Failed to close channel: {:?}", e);
+            }
+            if let Err(e) = session.connection.close(200, "judger shutting down").await {
+                error!("Failed to close connection: {:?}", e);
+            }
+            break;
+        }
+
+        error!("Lost connection to RabbitMQ, reconnecting...");
+    }
+
+    info!("Judger is shutting down...");
+
+    heartbeat_task.abort();
+    languages_task.abort();
+
+    if let Err(e) = judger::cleanup_isolate_box() {
+        error!("Failed to clean up isolate box: {:?}", e);
+    }
+
+    info!("Judger stopped");
+}
+
+/// Consume and handle jobs from the session. Returns `true` when a shutdown
+/// was requested and `false` when the connection was lost.
+async fn consume_jobs(
+    ctx: &context::AppContext,
+    broker: &broker::Broker,
+    session: &mut rabbitmq::Session,
+    shutdown_signal: &mut tokio::sync::watch::Receiver<bool>,
+) -> bool {
+    let mut scheduler = scheduler::JobScheduler::new(&ctx.config.capabilities);
+
+    loop {
+        let delivery = match scheduler.next_delivery(&session.channel, shutdown_signal).await {
+            Ok(Some(delivery)) => delivery,
+            Ok(None) => return true,
             Err(e) => {
                 error!("Error receiving message: {:?}", e);
+                return false;
+            }
+        };
+
+        let message =
+            match screen_delivery(ctx, broker, &session.channel, &mut scheduler, &delivery).await {
+                Screened::Handle(message) => message,
+                Screened::Settled => {
+                    if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+                        error!("Failed to ack message: {:?}", e);
+                    }
+                    continue;
+                }
+                Screened::Unsettled => {
+                    if let Err(e) = delivery
+                        .nack(BasicNackOptions {
+                            requeue: true,
+                            ..BasicNackOptions::default()
+                        })
+                        .await
+                    {
+                        error!("Failed to nack message: {:?}", e);
+                    }
+                    continue;
+                }
+            };
+
+        let job_key = finished_job_key(&message, &delivery);
+        let cancel = ctx.cancellations.token(cancel::dispatch_id(
+            &message.id(),
+            delivery.properties.message_id().as_ref().map(|id| id.as_str()),
+        ));
+        let job = handle_message(ctx, broker, message, &cancel);
+        tokio::pin!(job);
+
+        // On shutdown, give the in-flight job a grace period to finish
+        // before handing it back to the queue for another judger.
+        let finished = tokio::select! {
+            _ = &mut job => true,
+            _ = shutdown::requested(shutdown_signal) => {
+                info!(
+                    "Shutdown requested, waiting up to {}s for the current job",
+                    ctx.config.shutdown_grace.as_secs()
+                );
+                tokio::time::timeout(ctx.config.shutdown_grace, &mut job)
+                    .await
+                    .is_ok()
+            }
+        };
+
+        let settled = if finished {
+            // The result is in the outbox by now; if the ack is lost, the
+            // redelivered job is recognised and not judged again.
+            if let Some(job_key) = &job_key {
+                ctx.finished_jobs.insert(job_key);
+            }
+            delivery.ack(BasicAckOptions::default()).await
+        } else {
+            // Republished rather than nacked, so the interrupted attempt is
+            // not counted as a crash towards dead-lettering the job.
+            info!("Current job did not finish in time, requeueing it");
+            let attempts = dead_letter::delivery_attempts(&delivery);
+            match dead_letter::requeue_with_attempts(&session.channel, &delivery, attempts).await {
+                Ok(()) => delivery.ack(BasicAckOptions::default()).await,
+                Err(e) => {
+                    error!("Failed to requeue the current job: {:?}", e);
+                    delivery
+                        .nack(BasicNackOptions {
+                            requeue: true,
+                            ..BasicNackOptions::default()
+                        })
+                        .await
+                }
             }
+        };
+
+        // If the channel died while judging, the broker redelivers the job
+        // and the result waiting in the outbox is sent once we reconnect.
+        if let Err(e) = settled {
+            error!("Failed to settle message: {:?}", e);
         }
     }
 }
 
-async fn send_ack_message(channel: &lapin::Channel, ack: models::JudgerAck) {
-    #[derive(Serialize)]
-    struct JudgerAckWithJudgerId {
-        #[serde(flatten)]
-        ack: models::JudgerAck,
-        judger_id: String,
-    }
+async fn send_message(broker: &broker::Broker, message: JudgerMessage) {
+    broker.publish(message.queue(), message.to_bytes()).await;
+}
+
+async fn send_ack_message(broker: &broker::Broker, ack: protocol::JudgerAck) {
+    debug!("Sending ack message for job: {}", ack.id);
+    send_message(broker, JudgerMessage::Ack(Outgoing::new(ack))).await;
+}
+
+async fn send_result_message(broker: &broker::Broker, result: protocol::JudgerResult) {
+    debug!("Sending result message for job: {}", result.id);
+    send_message(broker, JudgerMessage::Result(Outgoing::new(result))).await;
+}
+
+async fn send_run_result_message(broker: &broker::Broker, result: protocol::RunResult) {
+    debug!("Sending run result message for job: {}", result.id);
+    send_message(broker, JudgerMessage::RunResult(Outgoing::new(result))).await;
+}
 
-    let ack_id = ack.id.clone(); // Clone the ID before moving ack
-    let judger_ack = JudgerAckWithJudgerId {
-        ack: ack,
-        judger_id: env_tool::env_or_default("JUDGER_ID", "unknown"),
+async fn send_rejudge_result_message(broker: &broker::Broker, result: protocol::RejudgeResult) {
+    debug!("Sending rejudge result message for job: {}", result.result.id);
+    send_message(broker, JudgerMessage::RejudgeResult(Outgoing::new(result))).await;
+}
+
+async fn send_heartbeat_message(ctx: &context::AppContext, broker: &broker::Broker) {
+    let status = ctx.status.snapshot();
+    let heartbeat = protocol::JudgerHeartbeat {
+        timestamp: Utc::now().timestamp(),
+        state: status.state,
+        job_id: status.job_id,
+        test_index: status.test_index,
+        jobs_completed: status.jobs_completed,
+        failures: status.failures,
+        uptime: status.uptime,
+        judger_version: env!("CARGO_PKG_VERSION").to_string(),
+        load_average: status::host_load(),
+        cache_free_bytes: ctx.cache.free_bytes(),
+        toolchains: ctx.languages.versions(),
+        capabilities: ctx.config.capabilities.clone(),
     };
 
-    #[derive(Serialize)]
-    struct AckMessage {
-        pattern: String,
-        data: JudgerAckWithJudgerId,
-    }
+    let message = JudgerMessage::Heartbeat(Outgoing::new(heartbeat));
 
-    let ack_json = serde_json::to_string(&AckMessage {
-        pattern: "judger.ack".to_string(),
-        data: judger_ack, // Now we can move ack safely
-    })
-    .unwrap();
-
-    debug!("Sending ack message for job: {}", ack_id);
-    channel
-        .basic_publish(
-            "",
-            "judger.ack",
-            BasicPublishOptions::default(),
-            ack_json.as_bytes(),
-            BasicProperties::default(),
-        )
-        .await
-        .expect("failed to publish message");
+    debug!("Sending heartbeat message for judger: {}", ctx.config.judger_id);
+    broker
+        .publish_volatile(message.queue(), &message.to_bytes())
+        .await;
 }
 
-async fn send_result_message(channel: &lapin::Channel, result: models::JudgerResult) {
-    #[derive(Serialize)]
-    struct JudgerResultWithJudgerId {
-        #[serde(flatten)]
-        result: models::JudgerResult,
-        judger_id: String,
+fn parse_job_message(delivery: &Delivery) -> Result<JobMessage, Box<dyn Error + Send + Sync>> {
+    debug!("Parsing job message with size: {} bytes", delivery.data.len());
+    let message = protocol::parse_job_message(&delivery.data)?;
+
+    match &message {
+        JobMessage::Judge(data) => info!(
+            "Parsed job message for job: {}, problem: {}",
+            data.id, data.problem_id
+        ),
+        JobMessage::Run(data) => info!(
+            "Parsed run message for job: {}, problem: {}",
+            data.id, data.problem_id
+        ),
+        JobMessage::Rejudge(data) => info!(
+            "Parsed rejudge message for job: {}, problem: {}",
+            data.id, data.problem_id
+        ),
     }
 
-    #[derive(Serialize)]
-    struct ResultMessage {
-        pattern: String,
-        data: JudgerResultWithJudgerId,
-    }
+    Ok(message)
+}
 
-    let result_id = result.id.clone(); // Clone the ID before moving result
-    let judger_result = JudgerResultWithJudgerId {
-        result: result,
-        judger_id: env_tool::env_or_default("JUDGER_ID", "unknown"),
-    };
+/// Key remembering that this dispatch of the job was finished, from the
+/// message id the server sets on every job it sends.
+fn finished_job_key(message: &JobMessage, delivery: &Delivery) -> Option<String> {
+    outbox::FinishedJobs::key(
+        message.id(),
+        delivery.properties.message_id().as_ref().map(|id| id.as_str()),
+    )
+}
 
-    let result_json = serde_json::to_string(&ResultMessage {
-        pattern: "judger.result".to_string(),
-        data: judger_result, // Now we can move result safely
-    })
-    .unwrap();
-
-    debug!("Sending result message for job: {}", result_id);
-    channel
-        .basic_publish(
-            "",
-            "judger.result",
-            BasicPublishOptions::default(),
-            result_json.as_bytes(),
-            BasicProperties::default(),
-        )
-        .await
-        .expect("failed to publish message");
+/// Outcome of screening a delivery.
+enum Screened {
+    /// The job should be handled now.
+    Handle(JobMessage),
+    /// The message was moved elsewhere; the caller only has to ack it.
+    Settled,
+    /// Moving the message failed; the caller requeues it so it is not lost.
+    Unsettled,
 }
 
-async fn send_heartbeat_message(channel: &lapin::Channel) {
-    let judger_id = env_tool::env_or_default("JUDGER_ID", "unknown");
+/// Reject messages that must not be judged here: unparseable ones go straight
+/// to the dead-letter queue, redelivered ones are counted and requeued, jobs
+/// not yet routed are moved to the queue of their priority and language, and
+/// jobs beyond this judger's capabilities are put back for another judger.
+async fn screen_delivery(
+    ctx: &context::AppContext,
+    broker: &broker::Broker,
+    channel: &lapin::Channel,
+    scheduler: &mut scheduler::JobScheduler,
+    delivery: &Delivery,
+) -> Screened {
+    let message = match parse_job_message(delivery) {
+        Ok(message) => message,
+        Err(e) => {
+            let reason = format!("Malformed job message: {}", e);
+            return match dead_letter::dead_letter(channel, delivery, &reason).await {
+                Ok(()) => Screened::Settled,
+                Err(_) => Screened::Unsettled,
+            };
+        }
+    };
 
-    #[derive(Serialize)]
-    struct HeartbeatMessage {
-        pattern: String,
-        data: models::JudgerHeartbeat,
+    // A job whose result was sent but whose delivery was never acked comes
+    // back after a crash; its result is already in the outbox.
+    if dead_letter::delivery_attempts(delivery) > 0
+        && finished_job_key(&message, delivery).is_some_and(|key| ctx.finished_jobs.contains(&key))
+    {
+        info!("Job {} was already judged, not judging it again", message.id());
+        return Screened::Settled;
     }
 
-    let heartbeat = models::JudgerHeartbeat {
-        judger_id: judger_id.clone(),
-        timestamp: Utc::now().timestamp(),
+    if delivery.redelivered {
+        return handle_redelivery(ctx, broker, channel, delivery, message).await;
+    }
+
+    // Producers publish jobs to an intake queue and only set their priority;
+    // route them to the queue of their priority and language. Queues are only
+    // declared for languages in the table, so the queue of an unknown language
+    // is declared here, where the job waits for a judger that knows it.
+    let priority = message.priority();
+    let routing_key = capabilities::routing_key(priority, message.language());
+    if delivery.exchange.as_str() != capabilities::JOB_EXCHANGE
+        || delivery.routing_key.as_str() != routing_key
+    {
+        if !ctx.languages.is_known(message.language()) {
+            info!("Job {} is in unknown language {}", message.id(), message.language());
+            if let Err(e) = rabbitmq::declare_job_queue(channel, priority, message.language()).await
+            {
+                error!("Failed to declare a queue for job {}: {:?}", message.id(), e);
+                return Screened::Unsettled;
+            }
+        }
+
+        info!("Routing job {} to {}", message.id(), routing_key);
+        return forward(channel, delivery, &routing_key).await;
+    }
+
+    // Only queues of supported languages are polled, so the language check is
+    // a safeguard. The problem is looked up again when judging; if it cannot
+    // be loaded here, judging reports the error.
+    let rejection = if !ctx.config.capabilities.supports_language(message.language()) {
+        Some(format!("language {} is not supported", message.language()))
+    } else {
+        match judger::get_problem(&ctx.db, &message.problem_id()).await {
+            Ok(problem) => ctx.config.capabilities.reject_problem(&problem),
+            Err(_) => None,
+        }
     };
 
-    let heartbeat_json = serde_json::to_string(&HeartbeatMessage {
-        pattern: "judger.heartbeat".to_string(),
-        data: heartbeat, // Now we can move result safely
-    })
-    .unwrap();
-
-    debug!("Sending heartbeat message for judger: {}", judger_id);
-    channel
-        .basic_publish(
-            "",
-            "judger.heartbeat",
-            BasicPublishOptions::default(),
-            heartbeat_json.as_bytes(),
-            BasicProperties::default(),
-        )
-        .await
-        .expect("failed to publish message");
+    if let Some(reason) = rejection {
+        info!("Declining job {}: {}", message.id(), reason);
+        scheduler.pause(&capabilities::job_queue(priority, message.language()));
+        return forward(channel, delivery, &routing_key).await;
+    }
+
+    Screened::Handle(message)
 }
 
-fn parse_job_message(delivery: &Delivery) -> models::JudgerJob {
-    #[derive(Deserialize)]
-    struct JobMessage {
-        // pattern: String,
-        data: models::JudgerJob,
+/// Publish a copy of `delivery` to the job exchange under `routing_key`. The
+/// original is settled once RabbitMQ confirms the copy, and requeued if it
+/// does not, so the job is never lost or judged twice.
+async fn forward(channel: &lapin::Channel, delivery: &Delivery, routing_key: &str) -> Screened {
+    match dead_letter::republish(
+        channel,
+        capabilities::JOB_EXCHANGE,
+        routing_key,
+        delivery,
+        delivery.properties.clone(),
+    )
+    .await
+    {
+        Ok(()) => Screened::Settled,
+        Err(e) => {
+            error!("Failed to move job to {}: {:?}", routing_key, e);
+            Screened::Unsettled
+        }
     }
+}
 
-    let body = String::from_utf8_lossy(&delivery.data);
-    debug!("Parsing job message with size: {} bytes", body.len());
-    let message: JobMessage = serde_json::from_str(&body).unwrap();
-
-    let data = message.data;
+/// A redelivered job was handed out before but never finished. Requeue it with
+/// the attempt recorded, or give up once it has crashed the judger too often.
+async fn handle_redelivery(
+    ctx: &context::AppContext,
+    broker: &broker::Broker,
+    channel: &lapin::Channel,
+    delivery: &Delivery,
+    message: JobMessage,
+) -> Screened {
+    let attempts = dead_letter::delivery_attempts(delivery);
+    if attempts >= ctx.config.max_delivery_attempts {
+        let reason = format!("Job was not finished after {} attempts", attempts);
+        if dead_letter::dead_letter(channel, delivery, &reason).await.is_err() {
+            return Screened::Unsettled;
+        }
 
-    info!(
-        "Parsed job message for job: {}, problem: {}",
-        data.id, data.problem_id
-    );
+        match message {
+            JobMessage::Judge(job) => {
+                send_result_message(
+                    broker,
+                    protocol::JudgerResult {
+                        id: job.id,
+                        log: reason,
+                        status: protocol::ResultStatus::IE,
+                        test_results: vec![],
+                        toolchain: None,
+                    },
+                )
+                .await
+            }
+            JobMessage::Run(job) => {
+                send_run_result_message(
+                    broker,
+                    protocol::RunResult {
+                        id: job.id,
+                        log: reason,
+                        status: protocol::ResultStatus::IE,
+                        ..protocol::RunResult::default()
+                    },
+                )
+                .await
+            }
+            JobMessage::Rejudge(job) => {
+                send_rejudge_result_message(
+                    broker,
+                    protocol::RejudgeResult {
+                        result: protocol::JudgerResult {
+                            id: job.id,
+                            log: reason,
+                            status: protocol::ResultStatus::IE,
+                            test_results: vec![],
+                            toolchain: None,
+                        },
+                        changes: vec![],
+                    },
+                )
+                .await
+            }
+        }
+        return Screened::Settled;
+    }
 
-    return data;
+    if let Err(e) = dead_letter::requeue_with_attempts(channel, delivery, attempts).await {
+        error!("Failed to requeue redelivered message: {:?}", e);
+        if dead_letter::dead_letter(channel, delivery, &e.to_string()).await.is_err() {
+            return Screened::Unsettled;
+        }
+    }
+    Screened::Settled
 }
 
-async fn handle_message(channel: &lapin::Channel, delivery: Delivery) {
-    let message = parse_job_message(&delivery);
+async fn handle_message(
+    ctx: &context::AppContext,
+    broker: &broker::Broker,
+    message: JobMessage,
+    cancel: &cancel::CancelToken<'_>,
+) {
+    match message {
+        JobMessage::Judge(job) => handle_judge_job(ctx, broker, job, cancel).await,
+        JobMessage::Run(job) => handle_run_job(ctx, broker, job, cancel).await,
+        JobMessage::Rejudge(job) => handle_rejudge_job(ctx, broker, job, cancel).await,
+    }
+}
 
+async fn handle_judge_job(
+    ctx: &context::AppContext,
+    broker: &broker::Broker,
+    message: protocol::JudgerJob,
+    cancel: &cancel::CancelToken<'_>,
+) {
     info!("Received message with id: {:?}", message.id);
 
     send_ack_message(
-        channel,
-        models::JudgerAck {
-            id: message.id.clone(),
+        broker,
+        protocol::JudgerAck {
+            id: message.id,
         },
     )
     .await;
 
     info!("Sent ack message with id: {:?}", message.id);
 
-    match judger::judge(&message).await {
+    match judger::judge(ctx, &message, cancel).await {
         Ok(result) => {
+            ctx.status.finish_job(false);
             info!("Judged successfully with id: {:?}", message.id);
-            send_result_message(channel, result).await;
+            send_result_message(broker, result).await;
         }
         Err(e) => {
+            ctx.status.finish_job(true);
             error!("Error judging message with id {:?}: {:?}", message.id, e);
             send_result_message(
-                channel,
-                models::JudgerResult {
-                    id: message.id.clone(),
+                broker,
+                protocol::JudgerResult {
+                    id: message.id,
                     log: e.to_string(),
-                    status: models::ResultStatus::IE,
+                    status: protocol::ResultStatus::IE,
                     test_results: vec![],
+                    toolchain: None,
                 },
             )
             .await;
         }
     }
 
-    delivery.ack(BasicAckOptions::default()).await.unwrap();
-
     info!("Sent result message with id: {:?}", message.id);
 }
+
+async fn handle_run_job(
+    ctx: &context::AppContext,
+    broker: &broker::Broker,
+    message: protocol::RunJob,
+    cancel: &cancel::CancelToken<'_>,
+) {
+    info!("Received run message with id: {:?}", message.id);
+
+    match judger::run(ctx, &message, cancel).await {
+        Ok(result) => {
+            ctx.status.finish_job(false);
+            info!("Ran successfully with id: {:?}", message.id);
+            send_run_result_message(broker, result).await;
+        }
+        Err(e) => {
+            ctx.status.finish_job(true);
+            error!("Error running message with id {:?}: {:?}", message.id, e);
+            send_run_result_message(
+                broker,
+                protocol::RunResult {
+                    id: message.id,
+                    log: e.to_string(),
+                    status: protocol::ResultStatus::IE,
+                    ..protocol::RunResult::default()
+                },
+            )
+            .await;
+        }
+    }
+
+    info!("Sent run result message with id: {:?}", message.id);
+}
+
+async fn handle_rejudge_job(
+    ctx: &context::AppContext,
+    broker: &broker::Broker,
+    message: protocol::RejudgeJob,
+    cancel: &cancel::CancelToken<'_>,
+) {
+    info!("Received rejudge message with id: {:?}", message.id);
+
+    send_ack_message(
+        broker,
+        protocol::JudgerAck {
+            id: message.id,
+        },
+    )
+    .await;
+
+    match judger::rejudge(ctx, &message, cancel).await {
+        Ok(result) => {
+            ctx.status.finish_job(false);
+            info!("Rejudged successfully with id: {:?}", message.id);
+            send_rejudge_result_message(broker, result).await;
+        }
+        Err(e) => {
+            ctx.status.finish_job(true);
+            error!("Error rejudging message with id {:?}: {:?}", message.id, e);
+            send_rejudge_result_message(
+                broker,
+                protocol::RejudgeResult {
+                    result: protocol::JudgerResult {
+                        id: message.id,
+                        log: e.to_string(),
+                        status: protocol::ResultStatus::IE,
+                        test_results: vec![],
+                        toolchain: None,
+                    },
+                    changes: vec![],
+                },
+            )
+            .await;
+        }
+    }
+
+    info!("Sent rejudge result message with id: {:?}", message.id);
+}
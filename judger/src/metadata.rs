@@ -5,7 +5,7 @@ use std::path::Path;
 
 pub fn metadata_file_to_hashmap<P: AsRef<Path>>(
     path: P,
-) -> Result<HashMap<String, String>, Box<dyn Error>> {
+) -> Result<HashMap<String, String>, Box<dyn Error + Send + Sync>> {
     let content = fs::read_to_string(path)?;
     let mut data = HashMap::new();
 
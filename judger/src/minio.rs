@@ -1,10 +1,13 @@
+use crate::env_tool;
 use aws_config::Region;
 use aws_credential_types::Credentials;
-use aws_sdk_s3::{
-    Client, config::Builder as S3ConfigBuilder, operation::get_object::GetObjectOutput,
-};
+use aws_sdk_s3::{Client, config::Builder as S3ConfigBuilder};
+use std::collections::HashMap;
 use std::error::Error;
-use crate::env_tool;
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+
+pub const TEST_CASES_BUCKET: &str = "test-cases";
 
 fn get_minio_endpoint_url() -> String {
     let minio_endpoint = env_tool::env_or_default("MINIO_ENDPOINT", "localhost");
@@ -16,9 +19,9 @@ pub fn make_minio_client() -> Client {
     let creds = Credentials::new(
         env_tool::env_or_default("MINIO_ACCESS_KEY", "minioadmin"), // access key
         env_tool::env_or_default("MINIO_SECRET_KEY", "minioadmin"), // secret key
-        None,                                                   // optional token
-        None,                                                   // optional expiry
-        "static",                                               // provider name
+        None,                                                       // optional token
+        None,                                                       // optional expiry
+        "static",                                                   // provider name
     );
 
     let config = S3ConfigBuilder::new()
@@ -31,8 +34,48 @@ pub fn make_minio_client() -> Client {
     Client::from_conf(config)
 }
 
-pub async fn minio_object_to_string(obj: GetObjectOutput) -> Result<String, Box<dyn Error>> {
-    let bytes = obj.body.collect().await?.into_bytes();
-    let content = String::from_utf8(bytes.to_vec())?;
-    return Ok(content);
+/// List every object under `prefix` and return a map of object key to ETag.
+pub async fn list_object_etags(
+    client: &Client,
+    bucket: &str,
+    prefix: &str,
+) -> Result<HashMap<String, String>, Box<dyn Error + Send + Sync>> {
+    let mut etags = HashMap::new();
+    let mut pages = client
+        .list_objects_v2()
+        .bucket(bucket)
+        .prefix(prefix)
+        .into_paginator()
+        .send();
+
+    while let Some(page) = pages.next().await {
+        for object in page?.contents() {
+            if let (Some(key), Some(etag)) = (object.key(), object.e_tag()) {
+                etags.insert(key.to_string(), etag.to_string());
+            }
+        }
+    }
+
+    Ok(etags)
+}
+
+/// Stream an object into `path` and return its ETag and size in bytes.
+pub async fn download_object_to_file(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    path: &Path,
+) -> Result<(Option<String>, u64), Box<dyn Error + Send + Sync>> {
+    let mut object = client.get_object().bucket(bucket).key(key).send().await?;
+    let etag = object.e_tag().map(str::to_string);
+
+    let mut file = tokio::fs::File::create(path).await?;
+    let mut size = 0u64;
+    while let Some(chunk) = object.body.try_next().await? {
+        file.write_all(&chunk).await?;
+        size += chunk.len() as u64;
+    }
+    file.flush().await?;
+
+    Ok((etag, size))
 }
@@ -1,57 +1,24 @@
 use serde::{Deserialize, Serialize};
-use sqlx;
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
 use strum_macros::{Display, EnumString};
+use tracing::warn;
 use uuid::Uuid;
 
-#[derive(Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct JudgerJob {
-    pub id: Uuid,
-    pub problem_id: Uuid,
-    pub source_code: String,
-    pub language: String,
-}
-
-#[derive(Deserialize, Serialize, Clone)]
-pub struct JudgerAck {
-    pub id: Uuid,
-}
+use crate::java::find_main_class;
+use crate::template::{self, CommandTemplate};
 
-#[derive(Deserialize, Serialize, Clone)]
-pub enum ResultStatus {
-    CE,
-    IE,
-    OK,
-}
-
-#[derive(Deserialize, Serialize, Debug, Clone)]
-pub enum Status {
-    AC,
-    WA,
-    RTE,
-    TLE,
-    MLE,
-}
-
-#[derive(Deserialize, Serialize, Clone)]
-pub struct TestResult {
+pub struct TestCaseRef {
+    pub subtask_slug: String,
+    pub subtask_position: i32,
     pub slug: String,
-    pub status: Status,
-    pub time: u64,
-    pub memory: u64,
+    pub position: i32,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
-pub struct JudgerResult {
-    pub id: Uuid,
-    pub log: String,
-    pub status: ResultStatus,
-    pub test_results: Vec<TestResult>,
-}
-
-pub struct TestCase {
-    pub input: String,
-    pub output: String,
+impl TestCaseRef {
+    pub fn full_slug(&self) -> String {
+        format!("{}/{}", self.subtask_slug, self.slug)
+    }
 }
 
 #[derive(EnumString, Display, PartialEq, Eq, Deserialize, Serialize, sqlx::Type)]
@@ -70,17 +37,378 @@ pub struct Problem {
     pub time_limit: u64,
     pub memory_limit: u64,
     pub id: Uuid,
+    /// Extra compiler flags set on the problem, appended to the flags of
+    /// languages whose compile command uses `{flags}`.
+    pub compile_flags: Vec<String>,
+}
+
+pub const MAIN_CLASS_PLACEHOLDER: &str = "{main_class}";
+
+/// The box directory as seen by commands run inside the sandbox.
+pub const SANDBOX_BOX_DIR: &str = "/box";
+
+/// Whether a problem may add `flag` to the compile command. Compilation runs
+/// outside the sandbox, so only defines, optimisation levels and libraries
+/// are accepted, never flags that read or write arbitrary files.
+pub fn is_allowed_problem_flag(flag: &str) -> bool {
+    let is_macro = |name: &str| {
+        name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+
+    if let Some(define) = flag.strip_prefix("-D") {
+        return is_macro(define.split_once('=').map_or(define, |(name, _)| name));
+    }
+    if let Some(name) = flag.strip_prefix("-U") {
+        return is_macro(name);
+    }
+    if let Some(library) = flag.strip_prefix("-l") {
+        return !library.is_empty()
+            && library
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-' | '.'));
+    }
+    matches!(flag, "-O0" | "-O1" | "-O2" | "-O3" | "-Os")
 }
 
+fn default_multiplier() -> f64 {
+    1.0
+}
+
+fn default_processes() -> u32 {
+    1
+}
+
+fn default_binary() -> String {
+    "main".to_string()
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct LanguageConfig {
-    pub language: &'static str,
-    pub ext: &'static str,
-    pub compile_command: &'static str,
-    pub run_command: &'static str,
+    pub id: String,
+    /// File the submission is written to inside the box.
+    pub source_file: String,
+    /// File the compiler writes, substituted for `{binary}`.
+    #[serde(default = "default_binary")]
+    pub binary: String,
+    /// Run in the box directory outside the sandbox. Empty for interpreted
+    /// languages.
+    #[serde(default)]
+    pub compile_command: CommandTemplate,
+    /// Flags substituted for `{flags}` in the compile command.
+    #[serde(default)]
+    pub compile_flags: Vec<String>,
+    /// System header precompiled with the compile command when the table is
+    /// loaded, such as `bits/stdc++.h`. Only for GCC compatible compilers.
+    #[serde(default)]
+    pub precompiled_header: Option<String>,
+    /// Syntax check run in the sandbox before judging, for languages without
+    /// a compile step. Failures are reported as compilation errors.
+    #[serde(default)]
+    pub check_command: CommandTemplate,
+    pub run_command: CommandTemplate,
+    #[serde(default = "default_multiplier")]
+    pub time_multiplier: f64,
+    #[serde(default = "default_multiplier")]
+    pub memory_multiplier: f64,
+    /// Processes and threads a submission may start in the sandbox. Managed
+    /// runtimes need more than one.
+    #[serde(default = "default_processes")]
+    pub processes: u32,
+    /// Address space the sandbox allows on top of the memory limit, for
+    /// runtimes that reserve much more than they use. The heap itself should
+    /// be capped with `{heap_mb}`, and memory used beyond the limit is still
+    /// reported as MLE.
+    #[serde(default)]
+    pub runtime_overhead_mb: u64,
+    /// Memory the runtime uses besides the heap, such as the code and class
+    /// metadata of the JVM. `{heap_mb}` is the memory limit minus this, so a
+    /// program filling its heap still fits the limit.
+    #[serde(default)]
+    pub runtime_baseline_mb: u64,
+    /// Extra environment variables for compiling and running.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Find the Java class holding the entry point and substitute it for
+    /// `{main_class}` in the file name and commands.
+    #[serde(default)]
+    pub detect_main_class: bool,
+    /// Prints the toolchain version, reported with every result. Run when the
+    /// table is loaded; the language is disabled if it fails.
+    pub version_command: Vec<String>,
+    /// Checks that the configured toolchain is the expected version.
+    #[serde(default, rename = "version_check")]
+    pub version_checks: Vec<VersionCheck>,
+}
+
+/// Commands of a language for one submission, with placeholders filled in.
+pub struct SubmissionCommands {
+    pub source_file: String,
+    pub compile_command: Vec<String>,
+    pub check_command: Vec<String>,
+    pub run_command: Vec<String>,
+}
+
+impl SubmissionCommands {
+    /// Search `dir` for headers before the system directories when
+    /// compiling, so precompiled headers there are picked up.
+    pub fn include_precompiled_headers(&mut self, dir: &Path) {
+        if !self.compile_command.is_empty() {
+            self.compile_command
+                .splice(1..1, ["-I".to_string(), dir.to_string_lossy().into_owned()]);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VersionCheck {
+    pub command: Vec<String>,
+    /// Text the output of `command` must contain.
+    pub expect: String,
+}
+
+impl LanguageConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.is_empty() {
+            return Err("id is empty".to_string());
+        }
+        let is_file_name = |name: &str| !name.is_empty() && !name.contains('/');
+        if !is_file_name(&self.source_file)
+            || self
+                .source_file
+                .replace(MAIN_CLASS_PLACEHOLDER, "")
+                .contains(['{', '}'])
+        {
+            return Err(format!("invalid source_file {:?}", self.source_file));
+        }
+        if !is_file_name(&self.binary) || self.binary.contains(['{', '}']) {
+            return Err(format!("invalid binary {:?}", self.binary));
+        }
+        if self.run_command.is_empty() {
+            return Err("run_command is empty".to_string());
+        }
+        for (name, command) in [
+            ("compile_command", &self.compile_command),
+            ("check_command", &self.check_command),
+            ("run_command", &self.run_command),
+        ] {
+            command.validate().map_err(|e| format!("{}: {}", name, e))?;
+            if command.placeholders().contains(template::FLAGS) && name != "compile_command" {
+                return Err(format!("{}: {{flags}} is only available when compiling", name));
+            }
+        }
+        for (name, multiplier) in [
+            ("time_multiplier", self.time_multiplier),
+            ("memory_multiplier", self.memory_multiplier),
+        ] {
+            if !multiplier.is_finite() || multiplier <= 0.0 {
+                return Err(format!("{} must be positive, got {}", name, multiplier));
+            }
+        }
+        if self.processes == 0 {
+            return Err("processes must be at least 1".to_string());
+        }
+        let uses_main_class = self.source_file.contains(MAIN_CLASS_PLACEHOLDER)
+            || [&self.compile_command, &self.check_command, &self.run_command]
+                .iter()
+                .any(|command| command.placeholders().contains(template::MAIN_CLASS));
+        if uses_main_class && !self.detect_main_class {
+            return Err(format!(
+                "{} is only available with detect_main_class",
+                MAIN_CLASS_PLACEHOLDER
+            ));
+        }
+        if !self.compile_flags.is_empty()
+            && !self.compile_command.placeholders().contains(template::FLAGS)
+        {
+            return Err("compile_flags are set but compile_command has no {flags}".to_string());
+        }
+        if let Some(flag) = self.compile_flags.iter().find(|flag| flag.is_empty()) {
+            return Err(format!("invalid compile flag {:?}", flag));
+        }
+        if let Some(header) = &self.precompiled_header {
+            if self.compile_command.is_empty() {
+                return Err("precompiled_header is set but compile_command is empty".to_string());
+            }
+            let is_relative = Path::new(header)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+            if header.is_empty() || !is_relative {
+                return Err(format!("invalid precompiled_header {:?}", header));
+            }
+        }
+        if self.version_command.first().is_none_or(|program| program.is_empty()) {
+            return Err("version_command is empty".to_string());
+        }
+        if let Some(check) = self
+            .version_checks
+            .iter()
+            .find(|check| check.command.first().is_none_or(|program| program.is_empty()) || check.expect.is_empty())
+        {
+            return Err(format!("incomplete version_check {:?}", check.command));
+        }
+        if let Some(name) = self
+            .env
+            .keys()
+            .find(|name| name.is_empty() || name.contains('='))
+        {
+            return Err(format!("invalid environment variable name {:?}", name));
+        }
+        Ok(())
+    }
+
+    /// File name and commands for one submission to `problem`. `box_dir` is
+    /// the box directory outside the sandbox, where compilation runs. Fails
+    /// with the compilation error to report when the submission has no usable
+    /// entry point.
+    pub fn commands_for(
+        &self,
+        source_code: &str,
+        problem: &Problem,
+        box_dir: &str,
+    ) -> Result<SubmissionCommands, String> {
+        let main_class = if self.detect_main_class {
+            find_main_class(source_code).ok_or_else(|| {
+                "No entry point found: declare a public class, or a class with a \
+                 `public static void main(String[] args)` method"
+                    .to_string()
+            })?
+        } else {
+            String::new()
+        };
+        let source_file = self.source_file.replace(MAIN_CLASS_PLACEHOLDER, &main_class);
+        let flags = self
+            .compile_flags
+            .iter()
+            .chain(&problem.compile_flags)
+            .cloned()
+            .collect::<Vec<String>>();
+        if !problem.compile_flags.is_empty()
+            && !self.compile_command.placeholders().contains(template::FLAGS)
+        {
+            warn!(
+                "Ignoring the compile flags of problem {} for {}, which takes no {{flags}}",
+                problem.id, self.id
+            );
+        }
+
+        let outside = template::Values {
+            source: &source_file,
+            binary: &self.binary,
+            box_dir,
+            memory_mb: problem.memory_limit / 1024,
+            heap_mb: (problem.memory_limit / 1024)
+                .saturating_sub(self.runtime_baseline_mb)
+                .max(1),
+            main_class: &main_class,
+            flags: &flags,
+        };
+        let inside = template::Values {
+            box_dir: SANDBOX_BOX_DIR,
+            ..outside
+        };
+
+        Ok(SubmissionCommands {
+            compile_command: self.compile_command.expand(&outside),
+            check_command: self.check_command.expand(&inside),
+            run_command: self.run_command.expand(&inside),
+            source_file,
+        })
+    }
+
+    /// The problem with its limits scaled by this language's multipliers.
+    pub fn scale_limits(&self, problem: Problem) -> Problem {
+        Problem {
+            time_limit: (problem.time_limit as f64 * self.time_multiplier).round() as u64,
+            memory_limit: (problem.memory_limit as f64 * self.memory_multiplier).round() as u64,
+            ..problem
+        }
+    }
 }
 
-#[derive(Serialize)]
-pub struct JudgerHeartbeat {
-    pub judger_id: String,
-    pub timestamp: i64,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::languages::{BUILTIN_LANGUAGES, parse_languages};
+
+    #[test]
+    fn defines_optimisation_levels_and_libraries_are_allowed() {
+        for flag in [
+            "-DONLINE_JUDGE",
+            "-D_DEBUG",
+            "-DN=100",
+            "-DNAME=a=b",
+            "-DEMPTY=",
+            "-UNDEBUG",
+            "-lm",
+            "-lstdc++",
+            "-lboost_system-mt.1",
+            "-O0",
+            "-O2",
+            "-O3",
+            "-Os",
+        ] {
+            assert!(is_allowed_problem_flag(flag), "{} was rejected", flag);
+        }
+    }
+
+    #[test]
+    fn flags_touching_files_or_the_toolchain_are_rejected() {
+        for flag in [
+            "-include",
+            "-include/etc/passwd",
+            "-o",
+            "-o/tmp/x",
+            "-B/tmp",
+            "-B.",
+            "@/tmp/flags",
+            "-specs=/tmp/specs",
+            "-fplugin=/tmp/plugin.so",
+            "-I/tmp",
+            "-Wl,-rpath,/tmp",
+            "-D",
+            "-D1X",
+            "-DA-lfoo",
+            "-DA/B=1",
+            "-U",
+            "-UA=1",
+            "-l",
+            "-l/tmp/lib",
+            "-O",
+            "-O4",
+            "-Ofast",
+            "",
+        ] {
+            assert!(!is_allowed_problem_flag(flag), "{:?} was allowed", flag);
+        }
+    }
+
+    #[test]
+    fn java_submission_is_named_after_its_main_class() {
+        let languages = parse_languages(BUILTIN_LANGUAGES).unwrap();
+        let java = languages.iter().find(|language| language.id == "JAVA_17").unwrap();
+        let problem = Problem {
+            io_mode: IoMode::Standard,
+            input_file: None,
+            output_file: None,
+            time_limit: 1000,
+            memory_limit: 256 * 1024,
+            id: Uuid::nil(),
+            compile_flags: vec![],
+        };
+
+        let commands = java
+            .commands_for("public final class Solution {}", &problem, "/box")
+            .unwrap();
+        assert_eq!(commands.source_file, "Solution.java");
+        assert!(commands.run_command.contains(&"Solution".to_string()));
+
+        let error = java
+            .commands_for("class Main { void main() {} }", &problem, "/box")
+            .err()
+            .unwrap();
+        assert!(error.starts_with("No entry point found"), "{}", error);
+    }
 }
@@ -0,0 +1,106 @@
+use std::cmp::Ordering;
+
+/// Compare two strings in "natural" order, so embedded numbers are compared
+/// by value instead of character by character (`test2` < `test10`).
+/// Ties on value fall back to the number of leading zeros, then to plain
+/// string comparison, so the order is total and deterministic.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let a_num = take_number(&mut a_chars);
+                let b_num = take_number(&mut b_chars);
+
+                let a_trimmed = a_num.trim_start_matches('0');
+                let b_trimmed = b_num.trim_start_matches('0');
+
+                let ordering = a_trimmed
+                    .len()
+                    .cmp(&b_trimmed.len())
+                    .then_with(|| a_trimmed.cmp(b_trimmed))
+                    .then_with(|| a_num.len().cmp(&b_num.len()));
+
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                if ca != cb {
+                    return ca.cmp(&cb);
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
+
+fn take_number<I: Iterator<Item = char>>(chars: &mut std::iter::Peekable<I>) -> String {
+    let mut number = String::new();
+    while let Some(&c) = chars.peek() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        number.push(c);
+        chars.next();
+    }
+    number
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut slugs: Vec<&str>) -> Vec<&str> {
+        slugs.sort_by(|a, b| natural_cmp(a, b));
+        slugs
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert_eq!(natural_cmp("test2", "test10"), Ordering::Less);
+        assert_eq!(natural_cmp("test10", "test2"), Ordering::Greater);
+        assert_eq!(natural_cmp("test10", "test10"), Ordering::Equal);
+        assert_eq!(
+            sorted(vec!["test10", "test1", "test2", "test100", "test9"]),
+            ["test1", "test2", "test9", "test10", "test100"]
+        );
+        assert_eq!(natural_cmp("1-10", "1-9"), Ordering::Greater);
+        assert_eq!(
+            natural_cmp("test18446744073709551616", "test18446744073709551615"),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn leading_zeros_only_break_ties() {
+        assert_eq!(natural_cmp("test02", "test10"), Ordering::Less);
+        assert_eq!(natural_cmp("test010", "test9"), Ordering::Greater);
+        assert_eq!(natural_cmp("test2", "test02"), Ordering::Less);
+        assert_eq!(natural_cmp("test02", "test002"), Ordering::Less);
+        assert_eq!(natural_cmp("test0", "test00"), Ordering::Less);
+        assert_eq!(
+            sorted(vec!["test002", "test1", "test02", "test2", "test01"]),
+            ["test1", "test01", "test2", "test02", "test002"]
+        );
+    }
+
+    #[test]
+    fn mixed_prefixes() {
+        assert_eq!(
+            sorted(vec!["sample", "test10", "b1", "test2", "a10", "a2", "2", "10", ""]),
+            ["", "2", "10", "a2", "a10", "b1", "sample", "test2", "test10"]
+        );
+        assert_eq!(natural_cmp("test", "test1"), Ordering::Less);
+        assert_eq!(natural_cmp("test1", "test1a"), Ordering::Less);
+        assert_eq!(natural_cmp("test1b", "test10a"), Ordering::Less);
+        assert_eq!(natural_cmp("9", "a"), Ordering::Less);
+        assert_eq!(natural_cmp("Test1", "test1"), Ordering::Less);
+    }
+}
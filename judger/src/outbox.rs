@@ -0,0 +1,289 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::env_tool;
+
+const PENDING_DIR: &str = "pending";
+const FINISHED_FILE: &str = "finished.log";
+
+/// How many finished jobs are remembered across restarts.
+const MAX_FINISHED: usize = 10_000;
+
+pub fn outbox_dir_from_env() -> PathBuf {
+    PathBuf::from(env_tool::env_or_default(
+        "JUDGER_OUTBOX_DIR",
+        "/var/local/lib/judger/outbox",
+    ))
+}
+
+fn write_durably(path: &Path, content: &[u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let tmp_path = path.with_extension("tmp");
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredMessage {
+    queue: String,
+    payload: String,
+}
+
+/// A message waiting in the outbox until the broker confirms it.
+pub struct OutboxMessage {
+    pub seq: u64,
+    pub queue: String,
+    pub payload: Vec<u8>,
+    /// Whether the message is on disk; if writing it failed, it is only kept
+    /// in memory.
+    pub stored: bool,
+}
+
+/// Messages that must reach the server, kept on disk until RabbitMQ confirms
+/// them so they survive a crash or restart of the judger.
+pub struct Outbox {
+    dir: PathBuf,
+    next_seq: AtomicU64,
+}
+
+impl Outbox {
+    /// Open the outbox in `dir`, keeping the messages left over from a
+    /// previous run for [`Outbox::load`].
+    pub fn open(dir: &Path) -> Result<Outbox, Box<dyn Error + Send + Sync>> {
+        let pending_dir = dir.join(PENDING_DIR);
+        fs::create_dir_all(&pending_dir)?;
+
+        // Leftovers of interrupted writes.
+        for entry in fs::read_dir(&pending_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "tmp") {
+                let _ = fs::remove_file(&path);
+            }
+        }
+
+        let entries = entries(&pending_dir)?;
+        if !entries.is_empty() {
+            info!(
+                "Recovered {} unsent messages from the outbox",
+                entries.len()
+            );
+        }
+
+        let next_seq = entries.last().map_or(0, |(seq, _)| seq + 1);
+        Ok(Outbox {
+            dir: pending_dir,
+            next_seq: AtomicU64::new(next_seq),
+        })
+    }
+
+    /// Read up to `limit` stored messages, oldest first, starting at `from`.
+    pub fn load(&self, from: u64, limit: usize) -> Vec<OutboxMessage> {
+        let entries = match entries(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Failed to list the outbox: {}", e);
+                return vec![];
+            }
+        };
+
+        let mut messages = vec![];
+        for (seq, path) in entries.into_iter().filter(|(seq, _)| *seq >= from) {
+            if messages.len() == limit {
+                break;
+            }
+
+            let stored: StoredMessage = match fs::read(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| serde_json::from_slice(&content).map_err(|e| e.to_string()))
+            {
+                Ok(stored) => stored,
+                Err(e) => {
+                    warn!("Dropping unreadable outbox entry {}: {}", path.display(), e);
+                    let _ = fs::remove_file(&path);
+                    continue;
+                }
+            };
+
+            messages.push(OutboxMessage {
+                seq,
+                queue: stored.queue,
+                payload: stored.payload.into_bytes(),
+                stored: true,
+            });
+        }
+        messages
+    }
+
+    fn path(&self, seq: u64) -> PathBuf {
+        self.dir.join(format!("{:020}.json", seq))
+    }
+
+    /// Write a message to disk before it is published.
+    pub fn store(&self, queue: &str, payload: &[u8]) -> OutboxMessage {
+        let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        let stored = StoredMessage {
+            queue: queue.to_string(),
+            payload: String::from_utf8_lossy(payload).into_owned(),
+        };
+
+        let result = serde_json::to_vec(&stored)
+            .map_err(|e| e.into())
+            .and_then(|content| write_durably(&self.path(seq), &content));
+        if let Err(e) = &result {
+            warn!(
+                "Failed to store message for {} in the outbox, it is only kept in memory: {}",
+                queue, e
+            );
+        }
+
+        OutboxMessage {
+            seq,
+            queue: stored.queue,
+            payload: stored.payload.into_bytes(),
+            stored: result.is_ok(),
+        }
+    }
+
+    /// Forget a message once the broker confirmed it.
+    pub fn remove(&self, seq: u64) {
+        let path = self.path(seq);
+        if let Err(e) = fs::remove_file(&path)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            warn!("Failed to remove outbox entry {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Sequence numbers and paths of the messages in `dir`, oldest first.
+fn entries(dir: &Path) -> Result<Vec<(u64, PathBuf)>, Box<dyn Error + Send + Sync>> {
+    let mut entries = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(seq) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".json"))
+            .and_then(|seq| seq.parse::<u64>().ok())
+        else {
+            continue;
+        };
+        entries.push((seq, path));
+    }
+    entries.sort_by_key(|(seq, _)| *seq);
+    Ok(entries)
+}
+
+#[derive(Default)]
+struct FinishedKeys {
+    keys: HashSet<String>,
+    order: VecDeque<String>,
+    /// Lines in the log file, which is compacted once it is twice as long
+    /// as needed.
+    logged: usize,
+}
+
+impl FinishedKeys {
+    fn trim(&mut self) {
+        while self.order.len() > MAX_FINISHED {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Jobs this judger finished, so a job redelivered after its result was sent
+/// is not judged a second time. Jobs are identified by their id together with
+/// the message id of the dispatch, as rejudges reuse the submission id and may
+/// be sent again with the same content.
+pub struct FinishedJobs {
+    path: PathBuf,
+    finished: Mutex<FinishedKeys>,
+}
+
+impl FinishedJobs {
+    pub fn open(dir: &Path) -> Result<FinishedJobs, Box<dyn Error + Send + Sync>> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(FINISHED_FILE);
+
+        let mut finished = FinishedKeys::default();
+        if let Ok(content) = fs::read_to_string(&path) {
+            for key in content.lines().filter(|line| !line.is_empty()) {
+                if finished.keys.insert(key.to_string()) {
+                    finished.order.push_back(key.to_string());
+                }
+            }
+        }
+        finished.trim();
+        compact(&path, &mut finished)?;
+
+        Ok(FinishedJobs {
+            path,
+            finished: Mutex::new(finished),
+        })
+    }
+
+    /// Key identifying one dispatch of a job. Messages without a message id
+    /// cannot be told apart from a later dispatch, so they have no key and are
+    /// judged again when redelivered.
+    pub fn key(job_id: Uuid, message_id: Option<&str>) -> Option<String> {
+        message_id
+            .filter(|message_id| !message_id.is_empty())
+            .map(|message_id| format!("{}/{}", job_id, message_id))
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.finished.lock().unwrap().keys.contains(key)
+    }
+
+    pub fn insert(&self, key: &str) {
+        let mut finished = self.finished.lock().unwrap();
+        if !finished.keys.insert(key.to_string()) {
+            return;
+        }
+        finished.order.push_back(key.to_string());
+
+        let appended = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| {
+                writeln!(file, "{}", key)?;
+                file.sync_data()
+            });
+        match appended {
+            Ok(()) => finished.logged += 1,
+            Err(e) => warn!("Failed to record finished job: {}", e),
+        }
+
+        finished.trim();
+        if finished.logged > 2 * MAX_FINISHED
+            && let Err(e) = compact(&self.path, &mut finished)
+        {
+            warn!("Failed to compact finished jobs: {}", e);
+        }
+    }
+}
+
+/// Rewrite the log with only the remembered jobs.
+fn compact(path: &Path, finished: &mut FinishedKeys) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut content = String::new();
+    for key in &finished.order {
+        content.push_str(key);
+        content.push('\n');
+    }
+    write_durably(path, content.as_bytes())?;
+    finished.logged = finished.order.len();
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use rustix::fs::{CWD, RenameFlags, renameat_with};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::info;
+
+use crate::env_tool;
+use crate::fingerprint::Fingerprint;
+use crate::models::LanguageConfig;
+use crate::template::Values;
+
+const KEY_FILE: &str = "key";
+
+pub fn pch_dir_from_env() -> PathBuf {
+    PathBuf::from(env_tool::env_or_default(
+        "JUDGER_PCH_DIR",
+        "/var/local/lib/judger/pch",
+    ))
+}
+
+/// Command precompiling `header` into `dir`: the compile command and flags of
+/// `language`, only compiling, as linker flags such as `-lm` would otherwise
+/// make it link the header.
+fn precompile_command(language: &LanguageConfig, dir: &Path, header: &str) -> Vec<String> {
+    let mut command = language.compile_command.expand(&Values {
+        source: &dir.join(header).to_string_lossy(),
+        binary: &dir.join(format!("{}.gch", header)).to_string_lossy(),
+        box_dir: &dir.to_string_lossy(),
+        memory_mb: 0,
+        heap_mb: 0,
+        main_class: "",
+        flags: &language.compile_flags,
+    });
+    command.insert(1, "-c".to_string());
+    command
+}
+
+/// Precompile `header` with the compile command and flags of `language` under
+/// `root`, unless it was already built with the same toolchain and command.
+/// Returns the directory to add to the include path.
+///
+/// The directory holds a header of the same name that includes the real one
+/// with `#include_next`, next to its precompiled copy. The compiler uses the
+/// copy when the flags of a submission match and otherwise silently falls back
+/// to the wrapper, so a stale or mismatched header only costs speed.
+///
+/// Submissions may be compiling against the directory while it is rebuilt on
+/// reload, so a new one is built next to it and swapped in atomically.
+pub fn prepare(
+    root: &Path,
+    language: &LanguageConfig,
+    header: &str,
+    toolchain: &str,
+) -> Result<PathBuf, String> {
+    let dir = root.join(&language.id);
+    let command = precompile_command(language, &dir, header);
+
+    let mut fingerprint = Fingerprint::default();
+    fingerprint.field(toolchain);
+    for arg in &command {
+        fingerprint.field(arg);
+    }
+    let key = fingerprint.finish();
+
+    if dir.join(format!("{}.gch", header)).is_file()
+        && fs::read_to_string(dir.join(KEY_FILE)).ok().as_deref() == Some(&key)
+    {
+        return Ok(dir);
+    }
+
+    info!("Precompiling {} for {}", header, language.id);
+    let tmp_dir = root.join(format!("{}.tmp", language.id));
+    let _ = fs::remove_dir_all(&tmp_dir);
+    let header_path = tmp_dir.join(header);
+    if let Some(parent) = header_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(&header_path, format!("#include_next <{}>\n", header)).map_err(|e| e.to_string())?;
+
+    let tmp_command = precompile_command(language, &tmp_dir, header);
+    let output = Command::new(&tmp_command[0])
+        .args(&tmp_command[1..])
+        .current_dir(&tmp_dir)
+        .envs(&language.env)
+        .output()
+        .map_err(|e| format!("`{}` failed: {}", tmp_command.join(" "), e))?;
+    if !output.status.success() {
+        let _ = fs::remove_dir_all(&tmp_dir);
+        return Err(format!(
+            "`{}` failed: {}",
+            tmp_command.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    fs::write(tmp_dir.join(KEY_FILE), &key).map_err(|e| e.to_string())?;
+
+    if dir.is_dir() {
+        renameat_with(CWD, &tmp_dir, CWD, &dir, RenameFlags::EXCHANGE)
+            .map_err(|e| format!("failed to replace {}: {}", dir.display(), e))?;
+        let _ = fs::remove_dir_all(&tmp_dir);
+    } else {
+        let _ = fs::remove_file(&dir);
+        fs::rename(&tmp_dir, &dir).map_err(|e| e.to_string())?;
+    }
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::languages::{BUILTIN_LANGUAGES, parse_languages};
+    use crate::models::{IoMode, Problem};
+    use uuid::Uuid;
+
+    /// Replace the file names of a command with their placeholders.
+    fn generalize(command: &[String], source: &str, binary: &str) -> Vec<String> {
+        command
+            .iter()
+            .map(|arg| match arg {
+                arg if arg == source => "{source}".to_string(),
+                arg if arg == binary => "{binary}".to_string(),
+                arg => arg.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn precompile_command_matches_submission_command() {
+        let dir = Path::new("/pch/CPP17");
+        let problem = Problem {
+            io_mode: IoMode::Standard,
+            input_file: None,
+            output_file: None,
+            time_limit: 1000,
+            memory_limit: 256 * 1024,
+            id: Uuid::nil(),
+            compile_flags: vec![],
+        };
+
+        let languages = parse_languages(BUILTIN_LANGUAGES).unwrap();
+        let with_header: Vec<_> = languages
+            .iter()
+            .filter(|language| language.precompiled_header.is_some())
+            .collect();
+        assert!(!with_header.is_empty());
+
+        for language in with_header {
+            let header = language.precompiled_header.as_deref().unwrap();
+            let mut precompile = precompile_command(language, dir, header);
+            assert_eq!(precompile.remove(1), "-c", "{}", language.id);
+
+            let mut submission = language.commands_for("", &problem, "/box").unwrap();
+            submission.include_precompiled_headers(dir);
+            let mut compile = submission.compile_command;
+            assert_eq!(
+                compile.drain(1..3).collect::<Vec<_>>(),
+                ["-I".to_string(), dir.to_string_lossy().into_owned()],
+                "{}",
+                language.id
+            );
+
+            assert_eq!(
+                generalize(
+                    &precompile,
+                    &dir.join(header).to_string_lossy(),
+                    &dir.join(format!("{}.gch", header)).to_string_lossy()
+                ),
+                generalize(&compile, &submission.source_file, &language.binary),
+                "{}",
+                language.id
+            );
+        }
+    }
+}
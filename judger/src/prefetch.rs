@@ -0,0 +1,70 @@
+use aws_sdk_s3::Client;
+use futures::StreamExt;
+use std::error::Error;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, error};
+
+use crate::cache::ProblemCache;
+
+/// Downloads the test data of upcoming test cases into the local cache while
+/// earlier tests are running. Results are yielded in test order; the number of
+/// tests fetched ahead is bounded by the configured concurrency.
+pub struct Prefetcher {
+    results: mpsc::Receiver<Result<(), Box<dyn Error + Send + Sync>>>,
+    handle: JoinHandle<()>,
+}
+
+impl Prefetcher {
+    pub fn start(
+        test_data: Arc<ProblemCache>,
+        client: Client,
+        test_cases: Vec<String>,
+        concurrency: usize,
+    ) -> Prefetcher {
+        let (sender, results) = mpsc::channel(concurrency);
+
+        let handle = tokio::spawn(async move {
+            let mut downloads = futures::stream::iter(test_cases)
+                .map(|slug| {
+                    let test_data = test_data.clone();
+                    let client = client.clone();
+                    async move {
+                        test_data.fetch(&client, &slug, "input").await?;
+                        test_data.fetch(&client, &slug, "output").await?;
+                        debug!("Prefetched test data for: {}", slug);
+                        Ok(())
+                    }
+                })
+                .buffered(concurrency);
+
+            while let Some(result) = downloads.next().await {
+                let failed = result.is_err();
+                if sender.send(result).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        Prefetcher { results, handle }
+    }
+
+    /// Wait until the data of the next test case is available locally.
+    pub async fn next(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.results.recv().await {
+            Some(Ok(())) => Ok(()),
+            Some(Err(e)) => {
+                error!("Failed to prefetch test data: {}", e);
+                Err(format!("Failed to fetch test data: {}", e).into())
+            }
+            None => Err("Test data prefetcher stopped unexpectedly".into()),
+        }
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
@@ -0,0 +1,550 @@
+//! Messages exchanged with the server over RabbitMQ.
+//!
+//! Every message uses the NestJS microservice envelope `{pattern, data}`, and
+//! every payload carries the protocol `version` it was written with. The JSON
+//! Schema in `schema/protocol.schema.json` is generated from these types
+//! (`judger schema`) and checked against fixture messages in the tests.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::capabilities::Capabilities;
+use crate::env_tool;
+use crate::status::JudgerState;
+
+/// Version of the message format written by this judger. Jobs with a newer
+/// version are dead-lettered instead of being misread.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Jobs from producers that predate the `version` field.
+fn legacy_version() -> u32 {
+    1
+}
+
+/// A job sent to the judgers, tagged by pattern.
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "pattern", content = "data")]
+pub enum JobMessage {
+    #[serde(rename = "judger.job")]
+    Judge(JudgerJob),
+    /// Not sent by the server yet: published by other tools, e.g. from the
+    /// RabbitMQ management UI, which read `judger.run_result` themselves.
+    #[serde(rename = "judger.run")]
+    Run(RunJob),
+    /// Not sent by the server yet, like runs; results go to
+    /// `judger.rejudge_result`.
+    #[serde(rename = "judger.rejudge")]
+    Rejudge(RejudgeJob),
+}
+
+impl JobMessage {
+    pub fn id(&self) -> Uuid {
+        match self {
+            JobMessage::Judge(job) => job.id,
+            JobMessage::Run(job) => job.id,
+            JobMessage::Rejudge(job) => job.id,
+        }
+    }
+
+    pub fn problem_id(&self) -> Uuid {
+        match self {
+            JobMessage::Judge(job) => job.problem_id,
+            JobMessage::Run(job) => job.problem_id,
+            JobMessage::Rejudge(job) => job.problem_id,
+        }
+    }
+
+    pub fn language(&self) -> &str {
+        match self {
+            JobMessage::Judge(job) => &job.language,
+            JobMessage::Run(job) => &job.language,
+            JobMessage::Rejudge(job) => &job.language,
+        }
+    }
+
+    /// Priority the job is queued with. Custom-input runs have none of their
+    /// own and are queued as normal jobs.
+    pub fn priority(&self) -> JobPriority {
+        match self {
+            JobMessage::Judge(job) => job.priority,
+            JobMessage::Run(_) => JobPriority::Normal,
+            JobMessage::Rejudge(job) => job.priority,
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        match self {
+            JobMessage::Judge(job) => job.version,
+            JobMessage::Run(job) => job.version,
+            JobMessage::Rejudge(job) => job.version,
+        }
+    }
+}
+
+/// Parse a job, rejecting versions newer than this judger understands.
+pub fn parse_job_message(data: &[u8]) -> Result<JobMessage, Box<dyn Error + Send + Sync>> {
+    let message: JobMessage = serde_json::from_slice(data)?;
+
+    if message.version() > PROTOCOL_VERSION {
+        return Err(format!(
+            "unsupported protocol version {} (this judger supports up to {})",
+            message.version(),
+            PROTOCOL_VERSION
+        )
+        .into());
+    }
+
+    Ok(message)
+}
+
+/// Payload of a message sent by the judger.
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[schemars(rename = "{T}Message")]
+pub struct Outgoing<T> {
+    pub version: u32,
+    pub judger_id: String,
+    #[serde(flatten)]
+    pub body: T,
+}
+
+impl<T> Outgoing<T> {
+    pub fn new(body: T) -> Outgoing<T> {
+        Outgoing {
+            version: PROTOCOL_VERSION,
+            judger_id: env_tool::env_or_default("JUDGER_ID", "unknown"),
+            body,
+        }
+    }
+}
+
+/// A message sent by the judger, tagged by pattern. Each pattern is published
+/// to the queue of the same name.
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "pattern", content = "data")]
+pub enum JudgerMessage {
+    #[serde(rename = "judger.ack")]
+    Ack(Outgoing<JudgerAck>),
+    #[serde(rename = "judger.result")]
+    Result(Outgoing<JudgerResult>),
+    /// Not consumed by the server yet; waits in its queue for whoever sent
+    /// the run.
+    #[serde(rename = "judger.run_result")]
+    RunResult(Outgoing<RunResult>),
+    /// Not consumed by the server yet; waits in its queue for whoever sent
+    /// the rejudge.
+    #[serde(rename = "judger.rejudge_result")]
+    RejudgeResult(Outgoing<RejudgeResult>),
+    #[serde(rename = "judger.heartbeat")]
+    Heartbeat(Outgoing<JudgerHeartbeat>),
+}
+
+impl JudgerMessage {
+    pub fn queue(&self) -> &'static str {
+        match self {
+            JudgerMessage::Ack(_) => "judger.ack",
+            JudgerMessage::Result(_) => "judger.result",
+            JudgerMessage::RunResult(_) => "judger.run_result",
+            JudgerMessage::RejudgeResult(_) => "judger.rejudge_result",
+            JudgerMessage::Heartbeat(_) => "judger.heartbeat",
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("protocol messages always serialize")
+    }
+}
+
+/// Every message of the protocol, in either direction.
+#[derive(JsonSchema)]
+#[serde(untagged)]
+#[schemars(title = "Judger protocol")]
+#[allow(dead_code)]
+enum ProtocolMessage {
+    Job(JobMessage),
+    Judger(JudgerMessage),
+}
+
+/// JSON Schema of every message of the protocol.
+pub fn schema() -> serde_json::Value {
+    let mut schema = schemars::schema_for!(ProtocolMessage).to_value();
+    schema["x-protocol-version"] = PROTOCOL_VERSION.into();
+    schema
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct JudgerJob {
+    pub id: Uuid,
+    pub problem_id: Uuid,
+    pub source_code: String,
+    pub language: String,
+    #[serde(default)]
+    pub priority: JobPriority,
+    #[serde(default = "legacy_version")]
+    pub version: u32,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum JobPriority {
+    /// Live contest submissions.
+    Contest,
+    /// Practice submissions.
+    #[default]
+    Normal,
+    /// Mass rejudges, only judged with spare capacity.
+    Rejudge,
+}
+
+impl JobPriority {
+    fn rejudge() -> JobPriority {
+        JobPriority::Rejudge
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobPriority::Contest => "contest",
+            JobPriority::Normal => "normal",
+            JobPriority::Rejudge => "rejudge",
+        }
+    }
+
+    /// Queue producers publish jobs of this priority to, before they are
+    /// routed by language.
+    pub fn queue_name(&self) -> &'static str {
+        match self {
+            JobPriority::Contest => "judger.job.contest",
+            JobPriority::Normal => "judger.job",
+            JobPriority::Rejudge => "judger.job.rejudge",
+        }
+    }
+}
+
+/// Rejudge of an existing submission. When `subtasks` and `tests` are both
+/// empty every test is run; otherwise only tests in one of `subtasks` or whose
+/// full `subtask/test` slug is in `tests`.
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RejudgeJob {
+    pub id: Uuid,
+    pub problem_id: Uuid,
+    pub source_code: String,
+    pub language: String,
+    #[serde(default = "JobPriority::rejudge")]
+    pub priority: JobPriority,
+    #[serde(default = "legacy_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub subtasks: Vec<String>,
+    #[serde(default)]
+    pub tests: Vec<String>,
+    #[serde(default)]
+    pub previous_results: Vec<PreviousTestResult>,
+}
+
+impl RejudgeJob {
+    pub fn to_judger_job(&self) -> JudgerJob {
+        JudgerJob {
+            id: self.id,
+            problem_id: self.problem_id,
+            source_code: self.source_code.clone(),
+            language: self.language.clone(),
+            priority: self.priority,
+            version: self.version,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+pub struct PreviousTestResult {
+    pub slug: String,
+    pub status: Status,
+}
+
+/// Custom-input run: compile and run the code once on `stdin` with the
+/// problem's limits, without checking the output.
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RunJob {
+    pub id: Uuid,
+    pub problem_id: Uuid,
+    pub source_code: String,
+    pub language: String,
+    pub stdin: String,
+    #[serde(default = "legacy_version")]
+    pub version: u32,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+pub struct JudgerAck {
+    pub id: Uuid,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Default)]
+pub enum ResultStatus {
+    CE,
+    #[default]
+    IE,
+    OK,
+    /// The job was cancelled before it finished; no verdict was produced.
+    #[serde(rename = "CANCELLED")]
+    Cancelled,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Status {
+    AC,
+    WA,
+    RTE,
+    TLE,
+    MLE,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+pub struct TestResult {
+    pub slug: String,
+    pub position: usize,
+    pub status: Status,
+    pub time: u64,
+    pub memory: u64,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+pub struct JudgerResult {
+    pub id: Uuid,
+    pub log: String,
+    pub status: ResultStatus,
+    pub test_results: Vec<TestResult>,
+    /// Version of the compiler or interpreter that judged the submission, if
+    /// it got that far.
+    #[serde(default)]
+    pub toolchain: Option<String>,
+}
+
+impl JudgerResult {
+    pub fn cancelled(id: Uuid) -> JudgerResult {
+        JudgerResult {
+            id,
+            log: "".to_string(),
+            status: ResultStatus::Cancelled,
+            test_results: vec![],
+            toolchain: None,
+        }
+    }
+}
+
+impl RunResult {
+    pub fn cancelled(id: Uuid) -> RunResult {
+        RunResult {
+            id,
+            status: ResultStatus::Cancelled,
+            ..RunResult::default()
+        }
+    }
+}
+
+/// A test whose verdict differs from the one before the rejudge. `None` means
+/// the test has no verdict on that side.
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+pub struct TestResultChange {
+    pub slug: String,
+    pub previous: Option<Status>,
+    pub current: Option<Status>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+pub struct RejudgeResult {
+    #[serde(flatten)]
+    pub result: JudgerResult,
+    pub changes: Vec<TestResultChange>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Default)]
+pub struct RunResult {
+    pub id: Uuid,
+    pub log: String,
+    pub status: ResultStatus,
+    /// TLE, MLE or RTE when the run did not finish normally, otherwise `None`.
+    pub verdict: Option<Status>,
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: Option<i32>,
+    pub exit_signal: Option<i32>,
+    pub time: u64,
+    pub memory: u64,
+    /// Version of the compiler or interpreter that ran the submission.
+    #[serde(default)]
+    pub toolchain: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct JudgerHeartbeat {
+    pub timestamp: i64,
+    pub state: JudgerState,
+    pub job_id: Option<Uuid>,
+    pub test_index: Option<usize>,
+    pub jobs_completed: u64,
+    pub failures: u64,
+    /// Seconds since the judger started.
+    pub uptime: u64,
+    /// Version of the judger binary, not of the protocol.
+    pub judger_version: String,
+    pub load_average: Option<[f64; 3]>,
+    pub cache_free_bytes: Option<u64>,
+    /// Toolchain version of each language this judger can judge.
+    pub toolchains: BTreeMap<String, String>,
+    #[serde(flatten)]
+    pub capabilities: Capabilities,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn fixture_dir(kind: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/protocol")
+            .join(kind)
+    }
+
+    fn fixtures(kind: &str) -> Vec<(PathBuf, serde_json::Value)> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(fixture_dir(kind))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty(), "no {} fixtures", kind);
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+                (path, value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn job_fixtures_round_trip() {
+        for (path, fixture) in fixtures("jobs") {
+            let message = parse_job_message(fixture.to_string().as_bytes())
+                .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            assert_eq!(
+                serde_json::to_value(&message).unwrap(),
+                fixture,
+                "{}",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn judger_message_fixtures_round_trip() {
+        for (path, fixture) in fixtures("messages") {
+            let message: JudgerMessage = serde_json::from_value(fixture.clone())
+                .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            assert_eq!(fixture["pattern"], message.queue(), "{}", path.display());
+            assert_eq!(
+                serde_json::to_value(&message).unwrap(),
+                fixture,
+                "{}",
+                path.display()
+            );
+        }
+    }
+
+    /// Validator for one definition of the committed schema, so the schema
+    /// the server is built against is checked and not only the generated one.
+    fn validator(definition: &str) -> jsonschema::Validator {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("schema/protocol.schema.json");
+        let mut schema: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        let root = schema.as_object_mut().unwrap();
+        root.remove("anyOf");
+        root.insert("$ref".to_string(), format!("#/$defs/{}", definition).into());
+        jsonschema::validator_for(&schema).unwrap()
+    }
+
+    fn assert_valid(validator: &jsonschema::Validator, path: &Path, fixture: &serde_json::Value) {
+        let errors: Vec<String> = validator
+            .iter_errors(fixture)
+            .map(|error| format!("{} at {}", error, error.instance_path()))
+            .collect();
+        assert!(errors.is_empty(), "{}: {}", path.display(), errors.join("; "));
+    }
+
+    #[test]
+    fn job_fixtures_match_schema() {
+        let validator = validator("JobMessage");
+        for (path, fixture) in fixtures("jobs") {
+            assert_valid(&validator, &path, &fixture);
+        }
+    }
+
+    #[test]
+    fn judger_message_fixtures_match_schema() {
+        let validator = validator("JudgerMessage");
+        for (path, fixture) in fixtures("messages") {
+            assert_valid(&validator, &path, &fixture);
+        }
+    }
+
+    #[test]
+    fn schema_rejects_malformed_messages() {
+        let validator = validator("JobMessage");
+        let (_, job) = fixtures("jobs").into_iter().next().unwrap();
+
+        let mut missing_field = job.clone();
+        missing_field["data"].as_object_mut().unwrap().remove("sourceCode");
+        let mut wrong_type = job.clone();
+        wrong_type["data"]["priority"] = "urgent".into();
+        let mut unknown_pattern = job;
+        unknown_pattern["pattern"] = "judger.unknown".into();
+
+        for message in [missing_field, wrong_type, unknown_pattern] {
+            assert!(!validator.is_valid(&message), "{} was accepted", message);
+        }
+    }
+
+    #[test]
+    fn legacy_job_defaults_to_version_one() {
+        let job = r#"{"pattern":"judger.job","data":{"id":"6f1c1f5e-3b8e-4a51-9d6a-2f3c1c0e8a11","problemId":"0b7d8a4e-9c2f-4f6e-8a1d-5e4b3c2a1f00","sourceCode":"","language":"CPP17"}}"#;
+        let message = parse_job_message(job.as_bytes()).unwrap();
+        assert_eq!(message.version(), 1);
+        assert_eq!(message.priority(), JobPriority::Normal);
+    }
+
+    #[test]
+    fn newer_job_version_is_rejected() {
+        let job = format!(
+            r#"{{"pattern":"judger.job","data":{{"id":"6f1c1f5e-3b8e-4a51-9d6a-2f3c1c0e8a11","problemId":"0b7d8a4e-9c2f-4f6e-8a1d-5e4b3c2a1f00","sourceCode":"","language":"CPP17","version":{}}}}}"#,
+            PROTOCOL_VERSION + 1
+        );
+        assert!(parse_job_message(job.as_bytes()).is_err());
+    }
+
+    /// Regenerate with `UPDATE_SCHEMA=1 cargo test`.
+    #[test]
+    fn schema_is_up_to_date() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("schema/protocol.schema.json");
+        let generated = format!("{}\n", serde_json::to_string_pretty(&schema()).unwrap());
+
+        if std::env::var("UPDATE_SCHEMA").is_ok() {
+            fs::write(&path, &generated).unwrap();
+        }
+
+        let committed = fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            committed == generated,
+            "{} is out of date, regenerate it with UPDATE_SCHEMA=1 cargo test",
+            path.display()
+        );
+    }
+}
@@ -1,4 +1,16 @@
+use lapin::{
+    Channel, Connection, ConnectionProperties, Consumer, ExchangeKind, options::*,
+    types::{AMQPValue, FieldTable},
+};
+use std::time::Duration;
+use tracing::info;
+
+use crate::cancel::{CANCEL_EXCHANGE, CANCEL_QUEUE_EXPIRY, cancel_queue};
+use crate::capabilities::{JOB_EXCHANGE, job_queue, routing_key};
+use crate::dead_letter::declare_dead_letter_queue;
 use crate::env_tool;
+use crate::protocol::JobPriority;
+use crate::scheduler::JOB_QUEUES;
 
 pub fn get_rabbitmq_url() -> String {
     let rabbitmq_host = env_tool::env_or_default("RABBITMQ_HOST", "localhost");
@@ -10,3 +22,196 @@ pub fn get_rabbitmq_url() -> String {
         rabbitmq_user, rabbitmq_pass, rabbitmq_host, rabbitmq_port
     )
 }
+
+/// Exponential reconnection delay, doubling from one second up to a minute.
+pub struct Backoff {
+    current: Duration,
+}
+
+impl Backoff {
+    const INITIAL: Duration = Duration::from_secs(1);
+    const MAX: Duration = Duration::from_secs(60);
+
+    pub fn new() -> Backoff {
+        Backoff {
+            current: Self::INITIAL,
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(Self::MAX);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current = Self::INITIAL;
+    }
+}
+
+pub struct Session {
+    pub connection: Connection,
+    pub channel: Channel,
+    pub cancel_consumer: Consumer,
+}
+
+/// Declare the queue of jobs of `priority` in `language`, bound to the job
+/// exchange.
+pub async fn declare_job_queue(
+    channel: &Channel,
+    priority: JobPriority,
+    language: &str,
+) -> Result<(), lapin::Error> {
+    let queue = job_queue(priority, language);
+    channel
+        .queue_declare(
+            &queue,
+            QueueDeclareOptions {
+                durable: true,
+                ..QueueDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    channel
+        .queue_bind(
+            &queue,
+            JOB_EXCHANGE,
+            &routing_key(priority, language),
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Connect to RabbitMQ and declare every queue the judger uses, with job
+/// queues for each of `languages`. Called again after every lost connection.
+pub async fn open_session(judger_id: &str, languages: &[String]) -> Result<Session, lapin::Error> {
+    let rabbitmq_url = get_rabbitmq_url();
+
+    let connection = Connection::connect(&rabbitmq_url, ConnectionProperties::default()).await?;
+
+    info!("Connected to RabbitMQ at: {}", rabbitmq_url);
+
+    let channel = connection.create_channel().await?;
+
+    //results are only dropped from the outbox once RabbitMQ confirms them
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await?;
+
+    //judger job queues, one per priority
+    for (priority, _) in JOB_QUEUES {
+        channel
+            .queue_declare(
+                priority.queue_name(),
+                QueueDeclareOptions {
+                    durable: true,
+                    ..QueueDeclareOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+    }
+
+    //jobs are routed by language through a topic exchange. Queues are
+    //declared for every known language, not only the supported ones, so a
+    //job for a language no running judger supports waits instead of being
+    //dropped as unroutable.
+    channel
+        .exchange_declare(
+            JOB_EXCHANGE,
+            ExchangeKind::Topic,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..ExchangeDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    for (priority, _) in JOB_QUEUES {
+        for language in languages {
+            declare_job_queue(&channel, priority, language).await?;
+        }
+    }
+
+    //result queues that only the judger produces to. The server does not
+    //send runs or rejudges yet, so their results are read by the tool that
+    //sent them
+    for queue in ["judger.run_result", "judger.rejudge_result"] {
+        channel
+            .queue_declare(
+                queue,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..QueueDeclareOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+    }
+
+    declare_dead_letter_queue(&channel).await?;
+
+    //cancel requests are broadcast to every judger
+    channel
+        .exchange_declare(
+            CANCEL_EXCHANGE,
+            ExchangeKind::Fanout,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..ExchangeDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await?;
+
+    //each judger has its own cancel queue, kept while it reconnects so no
+    //cancel request is missed, and removed by the broker once the judger is
+    //gone for good
+    let cancel_queue = cancel_queue(judger_id);
+    let mut cancel_queue_arguments = FieldTable::default();
+    cancel_queue_arguments.insert(
+        "x-expires".into(),
+        AMQPValue::LongLongInt(CANCEL_QUEUE_EXPIRY.as_millis() as i64),
+    );
+    channel
+        .queue_declare(
+            &cancel_queue,
+            QueueDeclareOptions {
+                durable: true,
+                ..QueueDeclareOptions::default()
+            },
+            cancel_queue_arguments,
+        )
+        .await?;
+
+    channel
+        .queue_bind(
+            &cancel_queue,
+            CANCEL_EXCHANGE,
+            "",
+            QueueBindOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    let cancel_consumer = channel
+        .basic_consume(
+            &cancel_queue,
+            "",
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await?;
+
+    Ok(Session {
+        connection,
+        channel,
+        cancel_consumer,
+    })
+}
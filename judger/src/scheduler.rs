@@ -0,0 +1,418 @@
+use lapin::{Channel, message::Delivery, options::BasicGetOptions};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+use crate::capabilities::{Capabilities, job_queue};
+use crate::protocol::JobPriority;
+
+/// How long to wait before polling again when every job queue is empty. The
+/// wait doubles with every idle round, up to `MAX_IDLE_POLL_INTERVAL`, so an
+/// idle judger does not keep hammering RabbitMQ with `basic_get`s.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const MAX_IDLE_POLL_INTERVAL: Duration = Duration::from_secs(4);
+
+/// How long a queue is skipped after this judger declined a job from it, so
+/// another judger gets the chance to pick the job up.
+const DECLINE_PAUSE: Duration = Duration::from_secs(5);
+
+/// Job queues by priority, with the share of judging capacity each gets when
+/// all of them have work. Idle capacity always goes to whichever queue has jobs.
+pub const JOB_QUEUES: [(JobPriority, u32); 3] = [
+    (JobPriority::Contest, 6),
+    (JobPriority::Normal, 3),
+    (JobPriority::Rejudge, 1),
+];
+
+/// Pulls jobs from the priority queues using smooth weighted round robin.
+///
+/// Jobs are fetched one at a time with `basic_get` rather than through
+/// consumers, so the judger never holds a low-priority job unacked while a
+/// contest job is waiting, and queued jobs stay available to idle judgers.
+///
+/// Each priority has an intake queue, whose jobs are routed by language, and
+/// one queue per language; only the languages this judger supports are polled.
+pub struct JobScheduler {
+    current: [i64; JOB_QUEUES.len()],
+    queues: Vec<Vec<String>>,
+    /// Rotates the language queues of a priority so none of them starves.
+    offsets: [usize; JOB_QUEUES.len()],
+    paused: HashMap<String, Instant>,
+    idle_interval: Duration,
+}
+
+impl JobScheduler {
+    pub fn new(capabilities: &Capabilities) -> JobScheduler {
+        let queues = JOB_QUEUES
+            .iter()
+            .map(|(priority, _)| {
+                let mut queues = vec![priority.queue_name().to_string()];
+                queues.extend(
+                    capabilities
+                        .languages
+                        .iter()
+                        .map(|language| job_queue(*priority, language)),
+                );
+                queues
+            })
+            .collect();
+
+        JobScheduler {
+            current: [0; JOB_QUEUES.len()],
+            queues,
+            offsets: [0; JOB_QUEUES.len()],
+            paused: HashMap::new(),
+            idle_interval: IDLE_POLL_INTERVAL,
+        }
+    }
+
+    /// Stop polling `queue` for a while after declining a job from it.
+    pub fn pause(&mut self, queue: &str) {
+        self.paused
+            .insert(queue.to_string(), Instant::now() + DECLINE_PAUSE);
+    }
+
+    /// Priority indices in the order they should be tried for the next job:
+    /// the weighted pick first, then the rest from highest to lowest priority.
+    fn next_order(&mut self) -> Vec<usize> {
+        let total: i64 = JOB_QUEUES.iter().map(|(_, weight)| *weight as i64).sum();
+
+        for (current, (_, weight)) in self.current.iter_mut().zip(JOB_QUEUES.iter()) {
+            *current += *weight as i64;
+        }
+
+        let picked = (0..JOB_QUEUES.len())
+            .max_by_key(|&i| (self.current[i], std::cmp::Reverse(i)))
+            .unwrap_or(0);
+        self.current[picked] -= total;
+
+        let mut order = vec![picked];
+        order.extend((0..JOB_QUEUES.len()).filter(|&i| i != picked));
+        order
+    }
+
+    /// Queues of one priority in polling order: the intake queue first, then
+    /// the language queues starting from a rotating offset.
+    fn priority_queues(&mut self, index: usize) -> Vec<String> {
+        let now = Instant::now();
+        self.paused.retain(|_, until| *until > now);
+
+        let (intake, languages) = self.queues[index].split_first().expect("intake queue");
+        let offset = self.offsets[index] % languages.len().max(1);
+        self.offsets[index] = offset + 1;
+
+        std::iter::once(intake)
+            .chain(languages[offset..].iter())
+            .chain(languages[..offset].iter())
+            .filter(|queue| !self.paused.contains_key(*queue))
+            .cloned()
+            .collect()
+    }
+
+    /// Wait for the next job, polling the queues while all of them are empty.
+    /// Returns `None` once a shutdown is requested.
+    pub async fn next_delivery(
+        &mut self,
+        channel: &Channel,
+        shutdown: &mut watch::Receiver<bool>,
+    ) -> Result<Option<Delivery>, lapin::Error> {
+        self.next_from(
+            async |queue| {
+                let message = channel.basic_get(queue, BasicGetOptions::default()).await?;
+                Ok(message.map(|message| message.delivery))
+            },
+            shutdown,
+        )
+        .await
+    }
+
+    /// Wait for the next job, fetching from a queue with `get`. A fetch is
+    /// never interrupted, as the job it returns would be left unacked; the
+    /// shutdown is checked before each one and while waiting between rounds.
+    async fn next_from<T, E>(
+        &mut self,
+        mut get: impl AsyncFnMut(&str) -> Result<Option<T>, E>,
+        shutdown: &mut watch::Receiver<bool>,
+    ) -> Result<Option<T>, E> {
+        loop {
+            for index in self.next_order() {
+                for queue in self.priority_queues(index) {
+                    if *shutdown.borrow() {
+                        return Ok(None);
+                    }
+                    if let Some(job) = get(&queue).await? {
+                        self.idle_interval = IDLE_POLL_INTERVAL;
+                        return Ok(Some(job));
+                    }
+                }
+            }
+
+            // Nothing was fetched, so undo this round's credits to keep the
+            // weights about actual work rather than idle polls.
+            self.current = [0; JOB_QUEUES.len()];
+            tokio::select! {
+                biased;
+                _ = shutdown.wait_for(|requested| *requested) => return Ok(None),
+                _ = tokio::time::sleep(self.idle_interval) => {}
+            }
+            self.idle_interval = (self.idle_interval * 2).min(MAX_IDLE_POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::convert::Infallible;
+
+    fn scheduler(languages: &[&str]) -> JobScheduler {
+        JobScheduler::new(&Capabilities {
+            languages: languages
+                .iter()
+                .map(|language| language.to_string())
+                .collect(),
+            interactive: false,
+            max_memory_mb: None,
+        })
+    }
+
+    /// Queues holding jobs named after their queue, polled like `basic_get`.
+    struct Broker {
+        queues: HashMap<String, VecDeque<String>>,
+        polled: Vec<String>,
+        shutdown: watch::Receiver<bool>,
+    }
+
+    impl Broker {
+        fn new(jobs: &[(&str, usize)]) -> Broker {
+            let queues = jobs
+                .iter()
+                .map(|(queue, count)| (queue.to_string(), vec![queue.to_string(); *count].into()))
+                .collect();
+            Broker {
+                queues,
+                polled: vec![],
+                shutdown: watch::channel(false).1,
+            }
+        }
+
+        async fn next(&mut self, scheduler: &mut JobScheduler) -> String {
+            scheduler
+                .next_from(
+                    async |queue| {
+                        self.polled.push(queue.to_string());
+                        Ok::<_, Infallible>(
+                            self.queues.get_mut(queue).and_then(VecDeque::pop_front),
+                        )
+                    },
+                    &mut self.shutdown,
+                )
+                .await
+                .unwrap()
+                .unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn busy_queues_share_capacity_by_weight() {
+        let mut scheduler = scheduler(&[]);
+        let mut broker = Broker::new(&[
+            ("judger.job.contest", 100),
+            ("judger.job", 100),
+            ("judger.job.rejudge", 100),
+        ]);
+
+        let mut picked = vec![];
+        for _ in 0..10 {
+            picked.push(broker.next(&mut scheduler).await);
+        }
+        let count = |queue: &str| picked.iter().filter(|job| *job == queue).count();
+        assert_eq!(count("judger.job.contest"), 6);
+        assert_eq!(count("judger.job"), 3);
+        assert_eq!(count("judger.job.rejudge"), 1);
+
+        // Smooth: contest jobs are interleaved rather than taken in one run.
+        let longest_run = picked
+            .chunk_by(|a, b| a == b)
+            .map(|run| run.len())
+            .max()
+            .unwrap();
+        assert!(longest_run <= 2, "{:?}", picked);
+    }
+
+    #[tokio::test]
+    async fn idle_capacity_goes_to_queues_with_jobs() {
+        let mut scheduler = scheduler(&[]);
+        let mut broker = Broker::new(&[("judger.job.rejudge", 5), ("judger.job", 5)]);
+
+        let mut picked = vec![];
+        for _ in 0..10 {
+            picked.push(broker.next(&mut scheduler).await);
+        }
+        assert_eq!(picked.iter().filter(|job| *job == "judger.job").count(), 5);
+        // Normal jobs outweigh rejudges until they run out.
+        assert!(
+            picked[..5]
+                .iter()
+                .filter(|job| *job == "judger.job")
+                .count()
+                >= 3
+        );
+    }
+
+    #[tokio::test]
+    async fn intake_queue_is_polled_before_rotating_language_queues() {
+        let mut scheduler = scheduler(&["CPP17", "PYTHON3"]);
+        let mut broker = Broker::new(&[("judger.job.CPP17", 10), ("judger.job.PYTHON3", 10)]);
+
+        assert_eq!(broker.next(&mut scheduler).await, "judger.job.CPP17");
+        assert_eq!(
+            &broker.polled[..3],
+            [
+                "judger.job.contest",
+                "judger.job.contest.CPP17",
+                "judger.job.contest.PYTHON3"
+            ]
+        );
+        assert_eq!(&broker.polled[3..], ["judger.job", "judger.job.CPP17"]);
+
+        // The next round over normal jobs starts from the other language.
+        let mut picked = vec![];
+        for _ in 0..4 {
+            picked.push(broker.next(&mut scheduler).await);
+        }
+        assert_eq!(
+            picked,
+            [
+                "judger.job.PYTHON3",
+                "judger.job.CPP17",
+                "judger.job.PYTHON3",
+                "judger.job.CPP17"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn paused_queues_are_skipped() {
+        let mut scheduler = scheduler(&["CPP17", "PYTHON3"]);
+        let mut broker = Broker::new(&[("judger.job.CPP17", 10), ("judger.job.PYTHON3", 10)]);
+
+        scheduler.pause("judger.job.CPP17");
+        for _ in 0..4 {
+            assert_eq!(broker.next(&mut scheduler).await, "judger.job.PYTHON3");
+        }
+        assert!(
+            !broker
+                .polled
+                .iter()
+                .any(|queue| queue == "judger.job.CPP17")
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_queues_are_polled_again() {
+        let mut scheduler = scheduler(&["CPP17"]);
+        let mut polled = vec![];
+        let (_sender, mut shutdown) = watch::channel(false);
+
+        let job = scheduler
+            .next_from(
+                async |queue| {
+                    polled.push(queue.to_string());
+                    // Every queue is empty on the first round.
+                    let found = polled.len() > 6 && queue == "judger.job.rejudge";
+                    Ok::<_, Infallible>(found.then_some(polled.len()))
+                },
+                &mut shutdown,
+            )
+            .await
+            .unwrap();
+        assert_eq!(job, Some(11));
+        // The idle round left no credit behind, so contest jobs come first again.
+        assert_eq!(
+            polled[6..],
+            [
+                "judger.job.contest",
+                "judger.job.contest.CPP17",
+                "judger.job",
+                "judger.job.CPP17",
+                "judger.job.rejudge"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn idle_polling_backs_off_until_a_job_comes() {
+        let mut scheduler = scheduler(&[]);
+        let (_sender, mut shutdown) = watch::channel(false);
+        let mut polls = 0;
+        let started = Instant::now();
+
+        // Three queues per round; the job shows up on the third round.
+        let job = scheduler
+            .next_from(
+                async |_| {
+                    polls += 1;
+                    Ok::<_, Infallible>((polls > 6).then_some(()))
+                },
+                &mut shutdown,
+            )
+            .await
+            .unwrap();
+        assert_eq!(job, Some(()));
+        assert!(started.elapsed() >= IDLE_POLL_INTERVAL * 3);
+        assert_eq!(scheduler.idle_interval, IDLE_POLL_INTERVAL);
+    }
+
+    #[tokio::test]
+    async fn errors_are_returned() {
+        let mut scheduler = scheduler(&[]);
+        let (_sender, mut shutdown) = watch::channel(false);
+        let result: Result<Option<()>, &str> = scheduler
+            .next_from(async |_| Err("closed"), &mut shutdown)
+            .await;
+        assert_eq!(result, Err("closed"));
+    }
+
+    #[tokio::test]
+    async fn shutdown_stops_polling_between_fetches() {
+        let mut scheduler = scheduler(&["CPP17"]);
+        let (sender, mut shutdown) = watch::channel(false);
+        let mut polled = vec![];
+
+        // Requested during a fetch, which still completes; no further queue
+        // is polled.
+        let job = scheduler
+            .next_from(
+                async |queue| {
+                    polled.push(queue.to_string());
+                    sender.send(true).unwrap();
+                    Ok::<Option<()>, Infallible>(None)
+                },
+                &mut shutdown,
+            )
+            .await
+            .unwrap();
+        assert_eq!(job, None);
+        assert_eq!(polled, ["judger.job.contest"]);
+    }
+
+    #[tokio::test]
+    async fn shutdown_interrupts_waiting_for_jobs() {
+        let mut scheduler = scheduler(&[]);
+        let (sender, mut shutdown) = watch::channel(false);
+
+        let waiting =
+            scheduler.next_from(async |_| Ok::<Option<()>, Infallible>(None), &mut shutdown);
+        tokio::pin!(waiting);
+        assert!(
+            futures_lite::future::poll_once(&mut waiting)
+                .await
+                .is_none()
+        );
+
+        sender.send(true).unwrap();
+        assert_eq!(waiting.await, Ok(None));
+    }
+}
@@ -0,0 +1,28 @@
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::watch;
+use tracing::info;
+
+/// Listen for SIGTERM/SIGINT in the background. The returned receiver flips
+/// to `true` once a shutdown has been requested.
+pub fn spawn_signal_listener() -> watch::Receiver<bool> {
+    let (sender, receiver) = watch::channel(false);
+
+    tokio::spawn(async move {
+        let mut sigterm = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+        let mut sigint = signal(SignalKind::interrupt()).expect("failed to listen for SIGINT");
+
+        tokio::select! {
+            _ = sigterm.recv() => info!("Received SIGTERM"),
+            _ = sigint.recv() => info!("Received SIGINT"),
+        }
+
+        let _ = sender.send(true);
+    });
+
+    receiver
+}
+
+/// Resolve once a shutdown has been requested.
+pub async fn requested(receiver: &mut watch::Receiver<bool>) {
+    let _ = receiver.wait_for(|requested| *requested).await;
+}
@@ -0,0 +1,106 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+use uuid::Uuid;
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum JudgerState {
+    Idle,
+    Compiling,
+    Running,
+}
+
+struct StatusInner {
+    state: JudgerState,
+    job_id: Option<Uuid>,
+    test_index: Option<usize>,
+    jobs_completed: u64,
+    failures: u64,
+}
+
+/// Snapshot of what the judger is doing, reported in heartbeats.
+pub struct StatusSnapshot {
+    pub state: JudgerState,
+    pub job_id: Option<Uuid>,
+    pub test_index: Option<usize>,
+    pub jobs_completed: u64,
+    pub failures: u64,
+    pub uptime: u64,
+}
+
+/// Live state of this judger, updated by the job handlers.
+pub struct JudgerStatus {
+    started_at: Instant,
+    inner: Mutex<StatusInner>,
+}
+
+impl JudgerStatus {
+    pub fn new() -> JudgerStatus {
+        JudgerStatus {
+            started_at: Instant::now(),
+            inner: Mutex::new(StatusInner {
+                state: JudgerState::Idle,
+                job_id: None,
+                test_index: None,
+                jobs_completed: 0,
+                failures: 0,
+            }),
+        }
+    }
+
+    pub fn set_compiling(&self, job_id: &Uuid) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.state = JudgerState::Compiling;
+        inner.job_id = Some(*job_id);
+        inner.test_index = None;
+    }
+
+    pub fn set_running(&self, job_id: &Uuid, test_index: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.state = JudgerState::Running;
+        inner.job_id = Some(*job_id);
+        inner.test_index = Some(test_index);
+    }
+
+    /// Mark the current job as done. `failed` is true when it ended in an
+    /// internal error.
+    pub fn finish_job(&self, failed: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.state = JudgerState::Idle;
+        inner.job_id = None;
+        inner.test_index = None;
+        inner.jobs_completed += 1;
+        if failed {
+            inner.failures += 1;
+        }
+    }
+
+    pub fn snapshot(&self) -> StatusSnapshot {
+        let inner = self.inner.lock().unwrap();
+        StatusSnapshot {
+            state: inner.state,
+            job_id: inner.job_id,
+            test_index: inner.test_index,
+            jobs_completed: inner.jobs_completed,
+            failures: inner.failures,
+            uptime: self.started_at.elapsed().as_secs(),
+        }
+    }
+}
+
+/// 1, 5 and 15 minute load averages of the host.
+pub fn host_load() -> Option<[f64; 3]> {
+    let content = fs::read_to_string("/proc/loadavg").ok()?;
+    let mut fields = content.split_whitespace().map(|v| v.parse::<f64>().ok());
+    Some([fields.next()??, fields.next()??, fields.next()??])
+}
+
+/// Free bytes on the filesystem holding `path`.
+pub fn free_disk_bytes(path: &Path) -> Option<u64> {
+    let stat = rustix::fs::statvfs(path).ok()?;
+    Some(stat.f_bavail * stat.f_frsize)
+}
@@ -0,0 +1,262 @@
+use serde::Deserialize;
+use std::collections::HashSet;
+
+/// Placeholders a command template may use.
+pub const SOURCE: &str = "source";
+pub const BINARY: &str = "binary";
+pub const BOX_DIR: &str = "box_dir";
+pub const MEMORY_MB: &str = "memory_mb";
+pub const HEAP_MB: &str = "heap_mb";
+pub const MAIN_CLASS: &str = "main_class";
+/// Expands to zero or more arguments, so it must be a whole argument.
+pub const FLAGS: &str = "flags";
+
+const PLACEHOLDERS: [&str; 7] = [SOURCE, BINARY, BOX_DIR, MEMORY_MB, HEAP_MB, MAIN_CLASS, FLAGS];
+
+enum Segment<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+/// Split an argument into text and `{name}` placeholders. `{{` and `}}` stand
+/// for literal braces.
+fn segments(arg: &str) -> Result<Vec<Segment<'_>>, String> {
+    let mut segments = vec![];
+    let mut rest = arg;
+
+    while let Some(index) = rest.find(['{', '}']) {
+        if index > 0 {
+            segments.push(Segment::Text(&rest[..index]));
+        }
+        let brace = &rest[index..index + 1];
+        if rest[index + 1..].starts_with(brace) {
+            segments.push(Segment::Text(brace));
+            rest = &rest[index + 2..];
+            continue;
+        }
+        if brace == "}" {
+            return Err(format!("unmatched `}}` in {:?}", arg));
+        }
+        let end = rest[index..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed `{{` in {:?}", arg))?;
+        segments.push(Segment::Placeholder(&rest[index + 1..index + end]));
+        rest = &rest[index + end + 1..];
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+
+    Ok(segments)
+}
+
+/// Values substituted for the placeholders of a template.
+pub struct Values<'a> {
+    pub source: &'a str,
+    pub binary: &'a str,
+    pub box_dir: &'a str,
+    pub memory_mb: u64,
+    pub heap_mb: u64,
+    pub main_class: &'a str,
+    pub flags: &'a [String],
+}
+
+/// A command given as its arguments, each of which may contain placeholders.
+/// Arguments are passed to the program as they are, without a shell, so they
+/// may contain spaces and quotes.
+#[derive(Deserialize, Default)]
+#[serde(transparent)]
+pub struct CommandTemplate(Vec<String>);
+
+impl CommandTemplate {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The program to run, unless it depends on the submission.
+    pub fn program(&self) -> Option<&str> {
+        let program = self.0.first()?;
+        let is_literal = segments(program)
+            .is_ok_and(|segments| segments.iter().all(|segment| matches!(segment, Segment::Text(_))));
+        (is_literal && !program.starts_with("./")).then_some(program.as_str())
+    }
+
+    /// Placeholders used anywhere in the command.
+    pub fn placeholders(&self) -> HashSet<&str> {
+        self.0
+            .iter()
+            .filter_map(|arg| segments(arg).ok())
+            .flatten()
+            .filter_map(|segment| match segment {
+                Segment::Placeholder(name) => Some(name),
+                Segment::Text(_) => None,
+            })
+            .collect()
+    }
+
+    /// Check that every placeholder is known and `{flags}` stands alone.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.0.first().is_some_and(|program| program.is_empty()) {
+            return Err("the program is empty".to_string());
+        }
+        for arg in &self.0 {
+            for segment in segments(arg)? {
+                let Segment::Placeholder(name) = segment else {
+                    continue;
+                };
+                if !PLACEHOLDERS.contains(&name) {
+                    return Err(format!("unknown placeholder {{{}}} in {:?}", name, arg));
+                }
+                if name == FLAGS && arg != "{flags}" {
+                    return Err(format!("{{flags}} must be a whole argument, got {:?}", arg));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The arguments with the placeholders filled in. Arguments that do not
+    /// pass `validate` are kept as they are, and unknown placeholders as
+    /// written.
+    pub fn expand(&self, values: &Values) -> Vec<String> {
+        let mut args = vec![];
+        for arg in &self.0 {
+            if arg == "{flags}" {
+                args.extend(values.flags.iter().cloned());
+                continue;
+            }
+
+            let Ok(segments) = segments(arg) else {
+                args.push(arg.clone());
+                continue;
+            };
+            let mut expanded = String::new();
+            for segment in segments {
+                match segment {
+                    Segment::Text(text) => expanded.push_str(text),
+                    Segment::Placeholder(SOURCE) => expanded.push_str(values.source),
+                    Segment::Placeholder(BINARY) => expanded.push_str(values.binary),
+                    Segment::Placeholder(BOX_DIR) => expanded.push_str(values.box_dir),
+                    Segment::Placeholder(MEMORY_MB) => {
+                        expanded.push_str(&values.memory_mb.to_string())
+                    }
+                    Segment::Placeholder(HEAP_MB) => expanded.push_str(&values.heap_mb.to_string()),
+                    Segment::Placeholder(MAIN_CLASS) => expanded.push_str(values.main_class),
+                    Segment::Placeholder(name) => {
+                        expanded.push('{');
+                        expanded.push_str(name);
+                        expanded.push('}');
+                    }
+                }
+            }
+            args.push(expanded);
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(args: &[&str]) -> CommandTemplate {
+        CommandTemplate(args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    fn expand(args: &[&str], flags: &[&str]) -> Vec<String> {
+        let flags: Vec<String> = flags.iter().map(|flag| flag.to_string()).collect();
+        template(args).expand(&Values {
+            source: "main.cpp",
+            binary: "main",
+            box_dir: "/box",
+            memory_mb: 256,
+            heap_mb: 224,
+            main_class: "Main",
+            flags: &flags,
+        })
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        assert_eq!(
+            expand(
+                &[
+                    "g++",
+                    "{source}",
+                    "-o",
+                    "{box_dir}/{binary}",
+                    "-Xmx{heap_mb}m",
+                    "{memory_mb}",
+                    "{main_class}",
+                ],
+                &[]
+            ),
+            ["g++", "main.cpp", "-o", "/box/main", "-Xmx224m", "256", "Main"]
+        );
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        let command = template(&["sh", "-c", "echo {{{source}}} }}{{"]);
+        assert!(command.validate().is_ok());
+        assert_eq!(command.placeholders(), HashSet::from([SOURCE]));
+        assert_eq!(
+            expand(&["sh", "-c", "echo {{{source}}} }}{{"], &[]),
+            ["sh", "-c", "echo {main.cpp} }{"]
+        );
+        assert_eq!(expand(&["{{source}}"], &[]), ["{source}"]);
+    }
+
+    #[test]
+    fn unknown_placeholders_are_rejected() {
+        let error = template(&["gcc", "{sources}"]).validate().unwrap_err();
+        assert!(error.contains("unknown placeholder {sources}"), "{}", error);
+        assert_eq!(
+            expand(&["gcc", "{sources}", "{source}"], &[]),
+            ["gcc", "{sources}", "main.cpp"]
+        );
+    }
+
+    #[test]
+    fn unmatched_braces_are_rejected() {
+        for arg in ["{source", "a{", "source}", "}", "{source}}"] {
+            assert!(template(&["gcc", arg]).validate().is_err(), "{:?} was accepted", arg);
+            assert_eq!(expand(&["gcc", arg], &[]), ["gcc", arg]);
+        }
+    }
+
+    #[test]
+    fn empty_program_is_rejected() {
+        assert!(template(&["", "{source}"]).validate().is_err());
+        assert!(template(&[]).validate().is_ok());
+    }
+
+    #[test]
+    fn flags_expand_to_zero_or_more_arguments() {
+        let command = ["gcc", "{flags}", "{source}"];
+        assert!(template(&command).validate().is_ok());
+        assert_eq!(expand(&command, &[]), ["gcc", "main.cpp"]);
+        assert_eq!(expand(&command, &["-O2"]), ["gcc", "-O2", "main.cpp"]);
+        assert_eq!(
+            expand(&command, &["-O2", "-DX=a b"]),
+            ["gcc", "-O2", "-DX=a b", "main.cpp"]
+        );
+    }
+
+    #[test]
+    fn flags_must_be_a_whole_argument() {
+        for arg in ["-{flags}", "{flags}{source}", "{flags} ", "x{flags}x"] {
+            let error = template(&["gcc", arg]).validate().unwrap_err();
+            assert!(error.contains("whole argument"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn program_is_only_known_when_literal() {
+        assert_eq!(template(&["g++", "{source}"]).program(), Some("g++"));
+        assert_eq!(template(&["{box_dir}/run"]).program(), None);
+        assert_eq!(template(&["./{binary}"]).program(), None);
+        assert_eq!(template(&["./run"]).program(), None);
+        assert_eq!(template(&[]).program(), None);
+    }
+}
@@ -0,0 +1,10 @@
+using System;
+
+public class Program
+{
+    public static void Main()
+    {
+        string[] parts = Console.ReadLine().Split(new[] { ' ' }, StringSplitOptions.RemoveEmptyEntries);
+        Console.WriteLine(long.Parse(parts[0]) + long.Parse(parts[1]));
+    }
+}
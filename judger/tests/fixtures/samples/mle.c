@@ -0,0 +1,18 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+int main(void) {
+    size_t block = (size_t)1 << 20;
+    int count = 0;
+    for (int i = 0; i < 1024; i++) {
+        char *memory = malloc(block);
+        if (memory == NULL) {
+            return 1;
+        }
+        memset(memory, 1, block);
+        count++;
+    }
+    printf("%d\n", count);
+    return 0;
+}
@@ -0,0 +1,11 @@
+#include <iostream>
+#include <vector>
+
+int main() {
+    std::vector<std::vector<char> > blocks;
+    for (int i = 0; i < 1024; i++) {
+        blocks.push_back(std::vector<char>(1 << 20, 1));
+    }
+    std::cout << blocks.size() << std::endl;
+    return 0;
+}
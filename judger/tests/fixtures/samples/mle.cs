@@ -0,0 +1,20 @@
+using System;
+using System.Collections.Generic;
+
+public class Program
+{
+    public static void Main()
+    {
+        List<byte[]> blocks = new List<byte[]>();
+        for (int i = 0; i < 4096; i++)
+        {
+            byte[] block = new byte[1 << 20];
+            for (int j = 0; j < block.Length; j += 4096)
+            {
+                block[j] = 1;
+            }
+            blocks.Add(block);
+        }
+        Console.WriteLine(blocks.Count);
+    }
+}
@@ -0,0 +1,5 @@
+const blocks = [];
+for (let i = 0; i < 512; i++) {
+    blocks.push(new Array(1 << 20).fill(i));
+}
+console.log(blocks.length);
@@ -0,0 +1,7 @@
+fun main() {
+    val blocks = ArrayList<ByteArray>()
+    repeat(1024) {
+        blocks.add(ByteArray(1 shl 20))
+    }
+    println(blocks.size)
+}
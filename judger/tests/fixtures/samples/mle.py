@@ -0,0 +1,2 @@
+blocks = [bytearray(1 << 20) for _ in range(1024)]
+print(len(blocks))
@@ -0,0 +1,10 @@
+#include <stdio.h>
+
+int main(void) {
+    volatile unsigned long long counter = 0;
+    for (;;) {
+        counter++;
+    }
+    printf("%llu\n", counter);
+    return 0;
+}
@@ -0,0 +1,10 @@
+#include <iostream>
+
+int main() {
+    volatile unsigned long long counter = 0;
+    for (;;) {
+        counter = counter + 1;
+    }
+    std::cout << counter << std::endl;
+    return 0;
+}
@@ -0,0 +1,12 @@
+public class Program
+{
+    static volatile int counter = 0;
+
+    public static void Main()
+    {
+        while (true)
+        {
+            counter++;
+        }
+    }
+}
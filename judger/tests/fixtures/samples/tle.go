@@ -0,0 +1,12 @@
+package main
+
+import "fmt"
+
+var counter uint64
+
+func main() {
+	for {
+		counter++
+	}
+	fmt.Println(counter)
+}
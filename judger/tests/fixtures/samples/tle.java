@@ -0,0 +1,9 @@
+public class Main {
+    static volatile long counter = 0;
+
+    public static void main(String[] args) {
+        while (true) {
+            counter++;
+        }
+    }
+}
@@ -0,0 +1,4 @@
+let counter = 0;
+for (;;) {
+    counter++;
+}
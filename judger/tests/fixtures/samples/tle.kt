@@ -0,0 +1,8 @@
+@Volatile
+var counter = 0L
+
+fun main() {
+    while (true) {
+        counter++
+    }
+}
@@ -0,0 +1,6 @@
+fn main() {
+    let mut counter: u64 = 0;
+    loop {
+        counter = std::hint::black_box(counter + 1);
+    }
+}
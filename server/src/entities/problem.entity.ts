@@ -56,6 +56,10 @@ export class Problem {
 	@Column({ nullable: true, type: 'varchar', length: 255 })
 	outputFile: string | null;
 
+	// Space separated compiler flags appended to the language flags, e.g. -DLOCAL_CHECK
+	@Column({ type: 'varchar', length: 255, default: '' })
+	compileFlags: string;
+
 	@Column({
 		type: 'enum',
 		enum: ProblemScoringMethod,
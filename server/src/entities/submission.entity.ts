@@ -36,6 +36,13 @@ export enum ProgramLanguage {
 
 	PYTHON2 = 'PYTHON2',
 	PYTHON3 = 'PYTHON3',
+	PYPY3 = 'PYPY3',
+
+	RUST = 'RUST',
+	GO = 'GO',
+	KOTLIN = 'KOTLIN',
+	CSHARP = 'CSHARP',
+	JAVASCRIPT = 'JAVASCRIPT',
 }
 
 @Entity('submissions')
@@ -86,6 +93,14 @@ export class Submission {
 	@Column({ type: 'text', default: '' })
 	log: string;
 
+	// Compiler or interpreter version reported by the judger
+	@Column({ nullable: true, type: 'varchar', length: 255 })
+	toolchain: string | null;
+
+	// Message id of the latest job sent to the judgers, which cancel requests refer to
+	@Column({ nullable: true, type: 'uuid' })
+	dispatchId: string | null;
+
 	@OneToMany(() => SubmissionResult, (result) => result.submission, { cascade: true })
 	results: SubmissionResult[];
 }
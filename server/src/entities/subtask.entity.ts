@@ -19,6 +19,9 @@ export class Subtask {
 	@Column({ type: 'varchar', length: 255 })
 	slug: string;
 
+	@Column({ type: 'int', default: 0 })
+	position: number;
+
 	@OneToMany(() => TestCase, (testCase) => testCase.subtask)
 	testCases: TestCase[];
 }
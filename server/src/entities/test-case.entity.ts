@@ -17,4 +17,7 @@ export class TestCase {
 
 	@Column({ type: 'varchar', length: 255 })
 	slug: string;
+
+	@Column({ type: 'int', default: 0 })
+	position: number;
 }
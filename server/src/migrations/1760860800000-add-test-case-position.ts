@@ -0,0 +1,15 @@
+import { MigrationInterface, QueryRunner } from 'typeorm';
+
+export class AddTestCasePosition1760860800000 implements MigrationInterface {
+	name = 'AddTestCasePosition1760860800000';
+
+	public async up(queryRunner: QueryRunner): Promise<void> {
+		await queryRunner.query(`ALTER TABLE "subtasks" ADD "position" integer NOT NULL DEFAULT '0'`);
+		await queryRunner.query(`ALTER TABLE "test_cases" ADD "position" integer NOT NULL DEFAULT '0'`);
+	}
+
+	public async down(queryRunner: QueryRunner): Promise<void> {
+		await queryRunner.query(`ALTER TABLE "test_cases" DROP COLUMN "position"`);
+		await queryRunner.query(`ALTER TABLE "subtasks" DROP COLUMN "position"`);
+	}
+}
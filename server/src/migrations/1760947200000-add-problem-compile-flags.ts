@@ -0,0 +1,13 @@
+import { MigrationInterface, QueryRunner } from 'typeorm';
+
+export class AddProblemCompileFlags1760947200000 implements MigrationInterface {
+	name = 'AddProblemCompileFlags1760947200000';
+
+	public async up(queryRunner: QueryRunner): Promise<void> {
+		await queryRunner.query(`ALTER TABLE "problems" ADD "compileFlags" character varying(255) NOT NULL DEFAULT ''`);
+	}
+
+	public async down(queryRunner: QueryRunner): Promise<void> {
+		await queryRunner.query(`ALTER TABLE "problems" DROP COLUMN "compileFlags"`);
+	}
+}
@@ -0,0 +1,13 @@
+import { MigrationInterface, QueryRunner } from 'typeorm';
+
+export class AddSubmissionToolchain1761033600000 implements MigrationInterface {
+	name = 'AddSubmissionToolchain1761033600000';
+
+	public async up(queryRunner: QueryRunner): Promise<void> {
+		await queryRunner.query(`ALTER TABLE "submissions" ADD "toolchain" character varying(255)`);
+	}
+
+	public async down(queryRunner: QueryRunner): Promise<void> {
+		await queryRunner.query(`ALTER TABLE "submissions" DROP COLUMN "toolchain"`);
+	}
+}
@@ -0,0 +1,27 @@
+import { MigrationInterface, QueryRunner } from 'typeorm';
+
+export class AddMoreLanguages1761120000000 implements MigrationInterface {
+	name = 'AddMoreLanguages1761120000000';
+
+	public async up(queryRunner: QueryRunner): Promise<void> {
+		await queryRunner.query(`ALTER TYPE "public"."submissions_language_enum" RENAME TO "submissions_language_enum_old"`);
+		await queryRunner.query(
+			`CREATE TYPE "public"."submissions_language_enum" AS ENUM('C99', 'C11', 'C17', 'C23', 'CPP03', 'CPP11', 'CPP14', 'CPP17', 'CPP20', 'CPP23', 'JAVA_8', 'JAVA_11', 'JAVA_17', 'PYTHON2', 'PYTHON3', 'PYPY3', 'RUST', 'GO', 'KOTLIN', 'CSHARP', 'JAVASCRIPT')`,
+		);
+		await queryRunner.query(
+			`ALTER TABLE "submissions" ALTER COLUMN "language" TYPE "public"."submissions_language_enum" USING "language"::"text"::"public"."submissions_language_enum"`,
+		);
+		await queryRunner.query(`DROP TYPE "public"."submissions_language_enum_old"`);
+	}
+
+	public async down(queryRunner: QueryRunner): Promise<void> {
+		await queryRunner.query(
+			`CREATE TYPE "public"."submissions_language_enum_old" AS ENUM('C99', 'C11', 'C17', 'C23', 'CPP03', 'CPP11', 'CPP14', 'CPP17', 'CPP20', 'CPP23', 'JAVA_8', 'JAVA_11', 'JAVA_17', 'PYTHON2', 'PYTHON3')`,
+		);
+		await queryRunner.query(
+			`ALTER TABLE "submissions" ALTER COLUMN "language" TYPE "public"."submissions_language_enum_old" USING "language"::"text"::"public"."submissions_language_enum_old"`,
+		);
+		await queryRunner.query(`DROP TYPE "public"."submissions_language_enum"`);
+		await queryRunner.query(`ALTER TYPE "public"."submissions_language_enum_old" RENAME TO "submissions_language_enum"`);
+	}
+}
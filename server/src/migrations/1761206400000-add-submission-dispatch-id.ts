@@ -0,0 +1,13 @@
+import { MigrationInterface, QueryRunner } from 'typeorm';
+
+export class AddSubmissionDispatchId1761206400000 implements MigrationInterface {
+	name = 'AddSubmissionDispatchId1761206400000';
+
+	public async up(queryRunner: QueryRunner): Promise<void> {
+		await queryRunner.query(`ALTER TABLE "submissions" ADD "dispatchId" uuid`);
+	}
+
+	public async down(queryRunner: QueryRunner): Promise<void> {
+		await queryRunner.query(`ALTER TABLE "submissions" DROP COLUMN "dispatchId"`);
+	}
+}
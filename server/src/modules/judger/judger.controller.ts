@@ -4,6 +4,7 @@ import { EventPattern, Payload } from '@nestjs/microservices';
 import { JudgerService } from './judger.service';
 
 export type JudgerAck = {
+	version: number;
 	id: string;
 	judger_id: string;
 };
@@ -12,6 +13,7 @@ export enum JudgerResultStatus {
 	CE = 'CE',
 	IE = 'IE',
 	OK = 'OK',
+	CANCELLED = 'CANCELLED',
 }
 
 export enum TestCaseStatus {
@@ -24,22 +26,41 @@ export enum TestCaseStatus {
 
 export type TestCaseResult = {
 	slug: string;
+	position: number;
 	status: TestCaseStatus;
 	time: number;
 	memory: number;
 };
 
 export type JudgerResult = {
+	version: number;
 	id: string;
 	judger_id: string;
 	log: string;
 	status: JudgerResultStatus;
 	test_results: TestCaseResult[];
+	toolchain: string | null;
 };
 
+export type JudgerState = 'idle' | 'compiling' | 'running';
+
 export type JudgerHeartbeat = {
+	version: number;
 	judger_id: string;
 	timestamp: number;
+	state: JudgerState;
+	job_id: string | null;
+	test_index: number | null;
+	jobs_completed: number;
+	failures: number;
+	uptime: number;
+	judger_version: string;
+	load_average: [number, number, number] | null;
+	cache_free_bytes: number | null;
+	toolchains: Record<string, string>;
+	languages: string[];
+	interactive: boolean;
+	max_memory_mb: number | null;
 };
 
 @Controller('judger')
@@ -0,0 +1,6 @@
+// Version of the judger message protocol, see judger/schema/protocol.schema.json
+export const JUDGER_PROTOCOL_VERSION = 1;
+
+// The server only sends judger.job and judger.cancel. Custom-input runs (judger.run, answered on
+// judger.run_result) and partial rejudges (judger.rejudge, answered on judger.rejudge_result)
+// are understood by the judger but not sent from here yet.
@@ -31,11 +31,12 @@ export class JudgerService {
 
 		const keys = await redis.keys('judger:*');
 
-		const judgers: { id: string; busy: boolean }[] = await Promise.all(
+		const judgers: { id: string; busy: boolean; status: JudgerHeartbeat | null }[] = await Promise.all(
 			keys.map(async (key) => {
 				const data = await redis.hGetAll(key);
 				const id = key.split(':')[1];
-				return { id, busy: data.busy == '1' };
+				const status = data.status ? (JSON.parse(data.status) as JudgerHeartbeat) : null;
+				return { id, busy: data.busy == '1', status };
 			}),
 		);
 		return judgers;
@@ -68,7 +69,11 @@ export class JudgerService {
 		this.logger.log(`Received judger_heartbeat: ${JSON.stringify(data)}`);
 
 		const client = this.redisService.getClient();
-		await client.multi().hSet(`judger:${data.judger_id}`, 'alive', 1).expire(`judger:${data.judger_id}`, 30).exec();
+		await client
+			.multi()
+			.hSet(`judger:${data.judger_id}`, { alive: 1, status: JSON.stringify(data) })
+			.expire(`judger:${data.judger_id}`, 30)
+			.exec();
 	}
 
 	async handleJudgerAck(data: JudgerAck) {
@@ -84,8 +89,24 @@ export class JudgerService {
 
 	async handleJudgerResult(data: JudgerResult) {
 		this.logger.log(`Received judger_result: ${JSON.stringify(data)}`);
+
+		if (data.status == JudgerResultStatus.CANCELLED) {
+			// The submission may have been deleted. Otherwise put it back to pending, so it can be judged again.
+			const submission = await this.submissionRepository.findOneBy({ id: data.id });
+			if (submission && submission.status == SubmissionStatus.JUDGING) {
+				submission.status = SubmissionStatus.PENDING;
+				await this.submissionRepository.save(submission);
+				await this.handleJudgerResultSocket(data, submission.id);
+				return;
+			}
+			const client = this.redisService.getClient();
+			await client.hSet(`judger:${data.judger_id}`, 'busy', 0);
+			this.judgerGateway.server.emit('judger_update', { id: data.judger_id, busy: false });
+			return;
+		}
 		const submission = await this.submissionService.findOne(data.id);
 		const problem = await this.problemService.getProblemById(submission.problem.id);
+		submission.toolchain = data.toolchain ?? null;
 
 		if (data.status == JudgerResultStatus.CE) {
 			submission.status = SubmissionStatus.COMPILATION_ERROR;
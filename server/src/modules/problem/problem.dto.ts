@@ -1,4 +1,4 @@
-import { IsArray, IsEnum, IsInt, IsNotEmpty, IsOptional, IsString, ValidateIf } from 'class-validator';
+import { IsArray, IsEnum, IsInt, IsNotEmpty, IsOptional, IsString, Matches, MaxLength, Min, ValidateIf } from 'class-validator';
 
 import { ToBoolean } from 'src/decorators/to-boolean.decorator';
 import { ToStringArray } from 'src/decorators/to-string-array.decorator';
@@ -6,6 +6,12 @@ import { Trim } from 'src/decorators/trim.decorator';
 import { UndefinedToNull } from 'src/decorators/undefine-to-null.decorator';
 import { Difficulty, IOMode, ProblemScoringMethod } from 'src/entities/problem.entity';
 
+// Defines, optimisation levels and libraries only, mirroring is_allowed_problem_flag in the judger.
+// Flags must be whitespace separated so each one is checked whole, as the judger splits them.
+const COMPILE_FLAG = String.raw`(?:-D[A-Za-z_]\w*(?:=\S*)?|-U[A-Za-z_]\w*|-l[\w+.-]+|-O[0-3s])`;
+const COMPILE_FLAGS_PATTERN = new RegExp(String.raw`^\s*(?:${COMPILE_FLAG}(?:\s+${COMPILE_FLAG})*)?\s*$`);
+const COMPILE_FLAGS_MESSAGE = 'compileFlags may only contain -D, -U, -l and -O0 to -O3 or -Os flags';
+
 export class CreateProblemDto {
 	@Trim()
 	@IsNotEmpty()
@@ -44,6 +50,13 @@ export class CreateProblemDto {
 	@IsString()
 	outputFile: string | null;
 
+	@IsOptional()
+	@Trim()
+	@IsString()
+	@MaxLength(255)
+	@Matches(COMPILE_FLAGS_PATTERN, { message: COMPILE_FLAGS_MESSAGE })
+	compileFlags?: string;
+
 	@IsNotEmpty()
 	@IsEnum(ProblemScoringMethod)
 	scoringMethod: ProblemScoringMethod;
@@ -96,6 +109,13 @@ export class UpdateProblemDto {
 	@IsString()
 	outputFile?: string;
 
+	@IsOptional()
+	@Trim()
+	@IsString()
+	@MaxLength(255)
+	@Matches(COMPILE_FLAGS_PATTERN, { message: COMPILE_FLAGS_MESSAGE })
+	compileFlags?: string;
+
 	@IsOptional()
 	@IsEnum(ProblemScoringMethod)
 	scoringMethod?: ProblemScoringMethod;
@@ -179,6 +199,11 @@ export class CreateSubtaskDto {
 	@IsNotEmpty()
 	@IsString()
 	name: string;
+
+	@IsOptional()
+	@IsInt()
+	@Min(0)
+	position?: number;
 }
 
 export class UpdateSubtaskDto {
@@ -186,6 +211,11 @@ export class UpdateSubtaskDto {
 	@Trim()
 	@IsString()
 	name?: string;
+
+	@IsOptional()
+	@IsInt()
+	@Min(0)
+	position?: number;
 }
 
 export class CreateTestCaseDto {
@@ -199,6 +229,11 @@ export class CreateTestCaseDto {
 
 	@IsString()
 	output = '';
+
+	@IsOptional()
+	@IsInt()
+	@Min(0)
+	position?: number;
 }
 
 export class UpdateTestCaseDto {
@@ -214,4 +249,9 @@ export class UpdateTestCaseDto {
 	@IsOptional()
 	@IsString()
 	output?: string;
+
+	@IsOptional()
+	@IsInt()
+	@Min(0)
+	position?: number;
 }
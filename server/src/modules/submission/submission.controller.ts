@@ -1,12 +1,14 @@
-import { Body, Controller, ForbiddenException, Get, Param, Post, Query, UseGuards } from '@nestjs/common';
+import { Body, Controller, Delete, ForbiddenException, Get, Param, Post, Query, UseGuards } from '@nestjs/common';
 
-import { GetAllSubmissionsDto, SubmitCodeDto } from './submission.dto';
+import { CancelJudgingDto, GetAllSubmissionsDto, SubmitCodeDto } from './submission.dto';
 import { SubmissionService } from './submission.service';
 import { GetOptionalUser } from 'src/decorators/get-optional-user.decorator';
 import { GetUser } from 'src/decorators/get-user.decorator';
+import { Roles } from 'src/decorators/roles.decorator';
 import { User, UserRole } from 'src/entities/user.entity';
 import { JwtAuthGuard } from 'src/guards/jwt-auth.guard';
 import { OptionalJwtAuthGuard } from 'src/guards/optional-jwt-auth.guard';
+import { RoleGuard } from 'src/guards/role.guard';
 
 @Controller('submission')
 export class SubmissionController {
@@ -53,4 +55,25 @@ export class SubmissionController {
 			submission: await this.submissionService.submitCode(body, user),
 		};
 	}
+
+	// Abort judging, e.g. of a bulk rejudge that is no longer wanted
+	@Post('cancel')
+	@UseGuards(JwtAuthGuard, RoleGuard)
+	@Roles(UserRole.ADMIN)
+	async cancelJudging(@Body() body: CancelJudgingDto) {
+		await this.submissionService.cancelJudging(body.ids);
+		return {
+			message: 'success',
+		};
+	}
+
+	@Delete(':id')
+	@UseGuards(JwtAuthGuard, RoleGuard)
+	@Roles(UserRole.ADMIN)
+	async remove(@Param('id') id: string) {
+		await this.submissionService.remove(id);
+		return {
+			message: 'Submission deleted successfully',
+		};
+	}
 }
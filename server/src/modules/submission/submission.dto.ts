@@ -1,4 +1,4 @@
-import { IsEnum, IsInt, IsOptional, IsString } from 'class-validator';
+import { IsEnum, IsInt, IsOptional, IsString, IsUUID } from 'class-validator';
 
 import { SubmissionStatus } from 'src/entities/submission.entity';
 import { ProgramLanguage } from 'src/entities/submission.entity';
@@ -42,6 +42,11 @@ export class SubmitCodeDto {
 	problemId: string;
 }
 
+export class CancelJudgingDto {
+	@IsUUID('all', { each: true })
+	ids: string[];
+}
+
 export interface StatusStatistic {
 	status: SubmissionStatus;
 	count: number;
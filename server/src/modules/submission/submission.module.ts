@@ -35,6 +35,24 @@ import { Submission } from 'src/entities/submission.entity';
 					},
 				}),
 			},
+			{
+				// Fanout exchange every judger listens on for cancelled jobs
+				name: 'JUDGER_CANCEL_EXCHANGE',
+				imports: [ConfigModule],
+				inject: [ConfigService],
+				useFactory: (configService: ConfigService) => ({
+					transport: Transport.RMQ,
+					options: {
+						urls: [
+							`amqp://${configService.get<string>('RABBITMQ_USER')}:${configService.get<string>('RABBITMQ_PASS')}@${configService.get<string>('RABBITMQ_HOST')}:${configService.get<number>('RABBITMQ_PORT')}`,
+						],
+						exchange: 'judger.cancel',
+						exchangeType: 'fanout',
+						wildcards: true,
+						noAssert: true,
+					},
+				}),
+			},
 		]),
 	],
 	exports: [TypeOrmModule, SubmissionService],
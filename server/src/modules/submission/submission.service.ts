@@ -1,8 +1,10 @@
 import { Inject, Injectable, NotFoundException } from '@nestjs/common';
-import { ClientProxy } from '@nestjs/microservices';
+import { ClientProxy, RmqRecordBuilder } from '@nestjs/microservices';
 import { InjectRepository } from '@nestjs/typeorm';
-import { Repository } from 'typeorm';
+import { randomUUID } from 'crypto';
+import { In, Repository } from 'typeorm';
 
+import { JUDGER_PROTOCOL_VERSION } from '../judger/judger.protocol';
 import { ProblemService } from '../problem/problem.service';
 import { UserService } from '../user/user.service';
 import { GetAllSubmissionsDto, LanguageStatistic, StatusStatistic, SubmitCodeDto } from './submission.dto';
@@ -19,6 +21,8 @@ export class SubmissionService {
 		private readonly problemService: ProblemService,
 		@Inject('JUDGER_JOB_QUEUE')
 		private readonly judgerJobQueue: ClientProxy,
+		@Inject('JUDGER_CANCEL_EXCHANGE')
+		private readonly judgerCancelExchange: ClientProxy,
 	) {}
 
 	async findOne(id: string) {
@@ -132,27 +136,57 @@ export class SubmissionService {
 
 		console.log(JSON.stringify(userWithContest));
 
+		const dispatchId = randomUUID();
 		const submission = this.submissionRepository.create({
 			sourceCode: body.code,
 			language: body.language,
 			problem,
 			author: user,
 			contest: userWithContest?.joiningContest,
+			dispatchId,
 		});
 
 		const savedSubmission = await this.submissionRepository.save(submission);
 
-		// Queue submission for judging
-		this.judgerJobQueue.emit('judger.job', {
+		// Queue submission for judging. The message id identifies this dispatch, so the judger
+		// can recognise a redelivery without confusing it with a later job for the same submission,
+		// and a cancel request only stops this dispatch.
+		const job = new RmqRecordBuilder({
 			id: savedSubmission.id,
 			problemId: problem.id,
 			sourceCode: body.code,
 			language: body.language,
-		});
+			priority: userWithContest?.joiningContest ? 'contest' : 'normal',
+			version: JUDGER_PROTOCOL_VERSION,
+		})
+			.setOptions({ messageId: dispatchId })
+			.build();
+		this.judgerJobQueue.emit('judger.job', job);
 
 		return savedSubmission;
 	}
 
+	// Stop judging the given submissions, whether they are still queued or already running.
+	// Judgers cancel by dispatch, so judging a submission again later is not affected.
+	async cancelJudging(ids: string[]) {
+		if (ids.length === 0) return;
+		const submissions = await this.submissionRepository.findBy({ id: In(ids) });
+		this.emitCancel(submissions);
+	}
+
+	async remove(id: string) {
+		const submission = await this.findOne(id);
+
+		this.emitCancel([submission]);
+		await this.submissionRepository.remove(submission);
+	}
+
+	private emitCancel(submissions: Submission[]) {
+		const ids = submissions.map((submission) => submission.dispatchId ?? submission.id);
+		if (ids.length === 0) return;
+		this.judgerCancelExchange.emit('judger.cancel', { ids });
+	}
+
 	async getSubmissionActivity(userId: string) {
 		// Get submissions for the past year grouped by date
 		const oneYearAgo = new Date();